@@ -0,0 +1,424 @@
+use std::num::{NonZeroUsize, ParseIntError};
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use png_secret::compress::{self, Codec};
+use png_secret::lsb::Channels;
+
+/// Hide secret messages inside PNG files.
+#[derive(Debug, Parser)]
+#[command(name = "png-secret", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Hide a message in a new chunk of the given type
+    Encode(EncodeArgs),
+    /// Print the message stored in the first chunk of the given type
+    Decode(DecodeArgs),
+    /// Remove the first chunk of the given type
+    Remove(RemoveArgs),
+    /// List every chunk in the file
+    Print(PrintArgs),
+    /// Read and write tEXt, zTXt and iTXt metadata
+    Text(TextArgs),
+    /// Check the file's structure against the PNG spec
+    Validate(ValidateArgs),
+    /// Inspect or change data appended after the IEND chunk
+    Trailer(TrailerArgs),
+    /// Look for signs of hidden data and score how suspicious each file is
+    Scan(ScanArgs),
+    /// Embed an Ed25519 signature over the image or a payload
+    Sign(SignArgs),
+    /// Check embedded signatures against a public key
+    Verify(VerifyArgs),
+    /// Create an Ed25519 key pair for sign and verify
+    Keygen(KeygenArgs),
+    /// Store a SHA-256 of the image data, to detect later changes to it
+    AddIntegrity(AddIntegrityArgs),
+    /// Check the image data against its stored SHA-256
+    CheckIntegrity(CheckIntegrityArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct EncodeArgs {
+    /// PNG file, directory or glob pattern, or - for standard input
+    pub file: PathBuf,
+    /// Four-letter chunk type, or auto for an inconspicuous one derived
+    /// from the passphrase or key file, or random without one. A derived
+    /// type is the same in every image and lets guesses be checked offline
+    pub chunk_type: String,
+    /// The message, unless read with --file, --message-file or
+    /// --message-stdin, in which case this is the output
+    #[arg(required_unless_present_any = ["attach", "message_file", "message_stdin"])]
+    pub message: Option<String>,
+    /// Where to write the result, a directory for several files or - for
+    /// standard output; defaults to overwriting the input files
+    pub output: Option<PathBuf>,
+    /// Read the message from a file, or - for standard input
+    #[arg(long, value_name = "PATH", conflicts_with = "message_stdin")]
+    pub message_file: Option<PathBuf>,
+    /// Read the message from standard input
+    #[arg(long)]
+    pub message_stdin: bool,
+    /// Hide a file of any kind, stored with its name, size and MIME type so
+    /// that `decode --output` can restore it
+    #[arg(
+        long = "file",
+        value_name = "PATH",
+        conflicts_with_all = ["message_file", "message_stdin"]
+    )]
+    pub attach: Option<PathBuf>,
+    /// MIME type stored with --file; guessed from its extension by default
+    #[arg(long, value_name = "TYPE", requires = "attach")]
+    pub mime_type: Option<String>,
+    #[command(flatten)]
+    pub secret: SecretArgs,
+    #[command(flatten)]
+    pub method: MethodArgs,
+    /// Split the message over several chunks of at most this many bytes
+    #[arg(long, value_name = "BYTES")]
+    pub max_chunk_size: Option<usize>,
+    /// ID (in hex) stored in each piece of a split message; random by default
+    #[arg(long, value_name = "HEX", value_parser = parse_payload_id)]
+    pub payload_id: Option<u64>,
+    /// Compress the message first: none, deflate, zstd or brotli
+    #[arg(long, value_name = "CODEC", default_value_t = Codec::None)]
+    pub compress: Codec,
+    /// Keep ancillary chunks marked unsafe to copy, which are otherwise
+    /// dropped when critical chunks change, as the PNG spec requires
+    #[arg(long)]
+    pub keep_unsafe: bool,
+    /// Keep the original of each file edited in place, named with this suffix
+    #[arg(long, value_name = "SUFFIX", value_parser = parse_backup_suffix)]
+    pub backup: Option<String>,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DecodeArgs {
+    /// PNG file, directory or glob pattern, or - for standard input
+    pub file: PathBuf,
+    /// Four-letter chunk type, or auto for an inconspicuous one derived
+    /// from the passphrase or key file
+    pub chunk_type: String,
+    #[command(flatten)]
+    pub secret: SecretArgs,
+    #[command(flatten)]
+    pub method: MethodArgs,
+    /// Which split message to reassemble when the chunks hold several
+    #[arg(long, value_name = "HEX", value_parser = parse_payload_id)]
+    pub payload_id: Option<u64>,
+    /// Write the message to this file instead of printing it, or to a file
+    /// named as stored by `encode --file` if this is a directory; always a
+    /// directory for several images
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// Replace a file already at the path --output writes to
+    #[arg(long, requires = "output")]
+    pub force: bool,
+    /// Refuse to decompress a message past this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = compress::DEFAULT_MAX_SIZE)]
+    pub max_decompressed_size: usize,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+fn parse_payload_id(id: &str) -> Result<u64, ParseIntError> {
+    u64::from_str_radix(id, 16)
+}
+
+fn parse_backup_suffix(suffix: &str) -> Result<String, String> {
+    if suffix.is_empty() {
+        return Err("the suffix cannot be empty".to_string());
+    }
+
+    Ok(suffix.to_string())
+}
+
+/// How the message is hidden in the image.
+#[derive(Debug, Args)]
+pub struct MethodArgs {
+    /// Store the message in its own chunk, or in the pixels' low bits
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+    /// Channels whose least-significant bits carry the message (lsb only)
+    #[arg(long, default_value_t = Channels::default())]
+    pub channels: Channels,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// A dedicated chunk of the given type
+    Chunk,
+    /// The least-significant bits of the image samples, tagged with the chunk type
+    Lsb,
+}
+
+/// Where to get the secret that encrypts or decrypts the message.
+#[derive(Debug, Args)]
+pub struct SecretArgs {
+    /// Passphrase the message is encrypted with
+    #[arg(long, conflicts_with = "key_file")]
+    pub passphrase: Option<String>,
+    /// File whose contents the message is encrypted with, instead of a
+    /// passphrase; - for standard input
+    #[arg(long)]
+    pub key_file: Option<PathBuf>,
+}
+
+/// How read commands print what they find.
+#[derive(Debug, Args)]
+pub struct FormatArgs {
+    /// Print plain text, or a JSON or YAML document per file for scripts
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-readable text
+    Text,
+    /// JSON, one document per line for several files
+    Json,
+    /// YAML, one document per file
+    Yaml,
+}
+
+/// How to process several files at once.
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// Include PNG files in subdirectories of directory inputs
+    #[arg(short, long)]
+    pub recursive: bool,
+    /// Process at most this many files at a time; defaults to one per CPU
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveArgs {
+    /// PNG file, directory or glob pattern, or - to edit standard input onto
+    /// standard output
+    pub file: PathBuf,
+    pub chunk_type: String,
+    /// Keep ancillary chunks marked unsafe to copy, which are otherwise
+    /// dropped when critical chunks change, as the PNG spec requires
+    #[arg(long)]
+    pub keep_unsafe: bool,
+    /// Keep the original of each file, named with this suffix
+    #[arg(long, value_name = "SUFFIX", value_parser = parse_backup_suffix)]
+    pub backup: Option<String>,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct PrintArgs {
+    /// PNG files, directories or glob patterns, or - for standard input
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// PNG files, directories or glob patterns, or - for standard input
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    /// Same as --format json
+    #[arg(long, conflicts_with = "format")]
+    pub json: bool,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// PNG files, directories or glob patterns, or - for standard input
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct SignArgs {
+    /// PNG file, directory or glob pattern, or - for standard input
+    pub file: PathBuf,
+    /// File holding the secret key, as made by keygen
+    #[arg(long, value_name = "PATH")]
+    pub key: PathBuf,
+    /// Sign the data of the chunks of this type instead of the critical
+    /// chunks
+    #[arg(long, value_name = "TYPE")]
+    pub chunk_type: Option<String>,
+    /// Where to write the result, a directory for several files or - for
+    /// standard output; defaults to overwriting the input files
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Keep the original of each file edited in place, named with this suffix
+    #[arg(long, value_name = "SUFFIX", value_parser = parse_backup_suffix)]
+    pub backup: Option<String>,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// PNG files, directories or glob patterns, or - for standard input
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// File holding the public key, as made by keygen
+    #[arg(long, value_name = "PATH")]
+    pub public_key: PathBuf,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// Where to write the secret key; the public key goes next to it with a
+    /// .pub extension added
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct AddIntegrityArgs {
+    /// PNG file, directory or glob pattern, or - for standard input
+    pub file: PathBuf,
+    /// Where to write the result, a directory for several files or - for
+    /// standard output; defaults to overwriting the input files
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Keep the original of each file edited in place, named with this suffix
+    #[arg(long, value_name = "SUFFIX", value_parser = parse_backup_suffix)]
+    pub backup: Option<String>,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckIntegrityArgs {
+    /// PNG files, directories or glob patterns, or - for standard input
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct TrailerArgs {
+    #[command(subcommand)]
+    pub command: TrailerCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrailerCommand {
+    /// Report how much data follows IEND and preview it
+    Show {
+        file: PathBuf,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Save the data after IEND
+    Extract {
+        file: PathBuf,
+        /// Where to write the data; defaults to standard output
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Remove the data after IEND
+    Strip {
+        file: PathBuf,
+        /// Where to write the result, - for standard output; defaults to
+        /// overwriting the input file
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace the data after IEND with a message or a file's contents
+    Write(TrailerWriteArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct TrailerWriteArgs {
+    pub file: PathBuf,
+    #[arg(long, required_unless_present = "input", conflicts_with = "input")]
+    pub message: Option<String>,
+    /// File whose contents are appended instead of a message; - for
+    /// standard input
+    #[arg(long)]
+    pub input: Option<PathBuf>,
+    /// Where to write the result, - for standard output; defaults to
+    /// overwriting the input file
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct TextArgs {
+    #[command(subcommand)]
+    pub command: TextCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TextCommand {
+    /// List every text entry with its keyword
+    List {
+        file: PathBuf,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Print the text stored under a keyword
+    Get {
+        file: PathBuf,
+        #[arg(long)]
+        keyword: String,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Store text under a keyword, replacing entries with the same keyword
+    Set(TextSetArgs),
+    /// Remove every entry with a keyword
+    Delete {
+        file: PathBuf,
+        #[arg(long)]
+        keyword: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct TextSetArgs {
+    pub file: PathBuf,
+    pub text: String,
+    #[arg(long)]
+    pub keyword: String,
+    /// Compress the text (zTXt, or a compressed iTXt)
+    #[arg(long)]
+    pub compressed: bool,
+    /// Store UTF-8 text in iTXt even if it would fit in tEXt or zTXt
+    #[arg(long)]
+    pub international: bool,
+    /// Language of the text, such as "en" or "ja"; implies --international
+    #[arg(long, value_name = "TAG")]
+    pub language: Option<String>,
+    /// The keyword translated into the text's language; implies --international
+    #[arg(long, value_name = "KEYWORD")]
+    pub translated_keyword: Option<String>,
+}
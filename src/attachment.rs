@@ -0,0 +1,234 @@
+use serde::Serialize;
+
+use crate::{Error, Result};
+
+/// Marks a payload produced by [`Attachment::to_bytes`].
+const MAGIC: [u8; 4] = *b"PSFL";
+const VERSION: u8 = 1;
+
+/// Magic, version, size and the lengths of the name and MIME type.
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 8 + 2 + 1;
+
+/// MIME type for files whose extension is not recognized.
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// A file hidden as a payload, with what is needed to restore it: its
+/// original name, size and MIME type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// The file's name without any directories. May be empty, as for data
+    /// read from standard input.
+    pub file_name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// An attachment's header, without its data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Metadata {
+    pub file_name: String,
+    pub mime_type: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    /// Wraps `data` as the file `file_name`, guessing the MIME type from
+    /// the name's extension.
+    pub fn new(file_name: &str, data: Vec<u8>) -> Attachment {
+        Attachment {
+            file_name: file_name.to_string(),
+            mime_type: mime_type_for(file_name).to_string(),
+            data,
+        }
+    }
+
+    /// The name, MIME type and size.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            file_name: self.file_name.clone(),
+            mime_type: self.mime_type.clone(),
+            size: self.data.len() as u64,
+        }
+    }
+
+    /// Serializes the attachment as a payload, checking that the name has
+    /// no directories and that the name and MIME type fit in the header.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        check_file_name(&self.file_name)?;
+
+        let name_length = u16::try_from(self.file_name.len()).map_err(|_| {
+            Error::InvalidArgument(format!(
+                "file name must be at most {} bytes",
+                u16::MAX
+            ))
+        })?;
+        let mime_length = u8::try_from(self.mime_type.len()).map_err(|_| {
+            Error::InvalidArgument(format!("MIME type must be at most {} bytes", u8::MAX))
+        })?;
+
+        let mut bytes = Vec::with_capacity(
+            HEADER_LENGTH + self.file_name.len() + self.mime_type.len() + self.data.len(),
+        );
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.data.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&name_length.to_be_bytes());
+        bytes.push(mime_length);
+        bytes.extend_from_slice(self.file_name.as_bytes());
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&self.data);
+        Ok(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Attachment {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if !is_attachment(bytes) {
+            return Err(Error::InvalidPayload("data is not an attached file".to_string()));
+        }
+
+        if bytes.len() < HEADER_LENGTH {
+            return Err(Error::InvalidPayload("file header is truncated".to_string()));
+        }
+
+        let version = bytes[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::InvalidPayload(format!(
+                "unsupported file header version {}",
+                version
+            )));
+        }
+
+        let mut size = [0; 8];
+        size.copy_from_slice(&bytes[5..13]);
+        let size = u64::from_be_bytes(size);
+        let name_length = u16::from_be_bytes([bytes[13], bytes[14]]) as usize;
+        let mime_length = bytes[15] as usize;
+
+        let rest = &bytes[HEADER_LENGTH..];
+        if rest.len() < name_length + mime_length {
+            return Err(Error::InvalidPayload("file header is truncated".to_string()));
+        }
+
+        let (file_name, rest) = rest.split_at(name_length);
+        let (mime_type, data) = rest.split_at(mime_length);
+        if data.len() as u64 != size {
+            return Err(Error::InvalidPayload(format!(
+                "file should be {} bytes, found {}",
+                size,
+                data.len()
+            )));
+        }
+
+        let attachment = Attachment {
+            file_name: String::from_utf8(file_name.to_vec())?,
+            mime_type: String::from_utf8(mime_type.to_vec())?,
+            data: data.to_vec(),
+        };
+
+        // Never trust a stored name to stay inside the output directory
+        check_file_name(&attachment.file_name).map_err(|_| {
+            Error::InvalidPayload("stored file name is not a plain name".to_string())
+        })?;
+
+        Ok(attachment)
+    }
+}
+
+/// Whether a payload starts with the header written by
+/// [`Attachment::to_bytes`].
+fn is_attachment(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Guesses a MIME type from a file name's extension, falling back to
+/// [`DEFAULT_MIME_TYPE`].
+pub fn mime_type_for(file_name: &str) -> &'static str {
+    let extension = match file_name.rsplit_once('.') {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => return DEFAULT_MIME_TYPE,
+    };
+
+    match extension.as_str() {
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "7z" => "application/x-7z-compressed",
+        "pem" => "application/x-pem-file",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        _ => DEFAULT_MIME_TYPE,
+    }
+}
+
+/// Names must be a single path component, so that restoring a file by its
+/// stored name cannot write outside the chosen directory.
+fn check_file_name(file_name: &str) -> Result<()> {
+    if file_name.contains(['/', '\\', '\0']) || file_name == "." || file_name == ".." {
+        return Err(Error::InvalidArgument(format!(
+            "'{}' is not a plain file name",
+            file_name
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let attachment = Attachment::new("keys.tar.gz", vec![0, 159, 146, 150, 255]);
+        assert_eq!(attachment.mime_type, "application/gzip");
+
+        let bytes = attachment.to_bytes().unwrap();
+        assert!(is_attachment(&bytes));
+        assert_eq!(Attachment::try_from(bytes.as_ref()).unwrap(), attachment);
+        assert_eq!(attachment.metadata().size, 5);
+    }
+
+    #[test]
+    fn test_mime_types() {
+        assert_eq!(mime_type_for("notes.TXT"), "text/plain");
+        assert_eq!(mime_type_for("archive.zip"), "application/zip");
+        assert_eq!(mime_type_for("README"), DEFAULT_MIME_TYPE);
+        assert_eq!(mime_type_for("data.unknown"), DEFAULT_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let bytes = Attachment::new("a.bin", vec![1; 32]).to_bytes().unwrap();
+
+        assert!(Attachment::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Attachment::try_from(&bytes[..HEADER_LENGTH - 1]).is_err());
+    }
+
+    #[test]
+    fn test_unsafe_names() {
+        assert!(Attachment::new("../etc/passwd", Vec::new()).to_bytes().is_err());
+        assert!(Attachment::new("..", Vec::new()).to_bytes().is_err());
+
+        let mut bytes = Attachment::new("aa.bin", Vec::new()).to_bytes().unwrap();
+        let name_start = HEADER_LENGTH;
+        bytes[name_start..name_start + 2].copy_from_slice(b"..");
+        bytes[name_start + 2] = b'/';
+        assert!(Attachment::try_from(bytes.as_ref()).is_err());
+    }
+}
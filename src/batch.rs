@@ -0,0 +1,263 @@
+use std::error::Error as _;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use rayon::prelude::*;
+
+use png_secret::{Error, Result};
+
+use crate::stdio;
+
+/// One file to process.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub path: PathBuf,
+    /// Where the file sits under the directory or pattern it was found
+    /// through, used to lay out outputs the same way.
+    pub relative: PathBuf,
+}
+
+/// The files named by a command's inputs.
+#[derive(Debug)]
+pub struct Batch {
+    pub targets: Vec<Target>,
+    /// Whether the inputs named a set of files rather than a single file.
+    /// Single files keep the plain output and error of a one-off command.
+    pub is_batch: bool,
+    /// Whether a batch prints each file's name above its output. Off for
+    /// output that names the file itself.
+    pub headers: bool,
+    /// Whether the jobs' output goes to stderr, because the edited PNG is
+    /// being written to stdout.
+    pub output_to_stderr: bool,
+}
+
+impl Batch {
+    /// Expands files, directories and glob patterns into the files to
+    /// process. Directories contribute their `.png` files, and those of
+    /// their subdirectories if `recursive` is set.
+    pub fn expand(inputs: &[PathBuf], recursive: bool) -> Result<Batch> {
+        let mut targets = Vec::new();
+        let mut is_batch = inputs.len() > 1;
+
+        for input in inputs {
+            if input.is_dir() {
+                is_batch = true;
+                walk(input, input, recursive, &mut targets)?;
+            } else if !input.exists() && is_pattern(input) {
+                is_batch = true;
+                expand_pattern(input, recursive, &mut targets)?;
+            } else {
+                targets.push(Target {
+                    path: input.clone(),
+                    relative: input.file_name().map(PathBuf::from).unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(Batch {
+            targets,
+            is_batch,
+            headers: true,
+            output_to_stderr: false,
+        })
+    }
+
+    /// Checks that `output` can take the results of every file before any
+    /// are processed.
+    pub fn check_output(&self, output: Option<&Path>) -> Result<()> {
+        if self.is_batch && output.is_some_and(stdio::is_stdio) {
+            return Err(Error::InvalidArgument(
+                "several files cannot be written to standard output".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Where to write the result for `target`. A batch writes into the
+    /// `output` directory, mirroring the inputs' layout; a single file uses
+    /// `output` as is. `None` means editing in place.
+    pub fn output_path(&self, target: &Target, output: Option<&Path>) -> Result<Option<PathBuf>> {
+        let Some(output) = output else {
+            return Ok(None);
+        };
+
+        if !self.is_batch {
+            return Ok(Some(output.to_path_buf()));
+        }
+
+        if output.is_file() {
+            return Err(Error::InvalidArgument(format!(
+                "output {} must be a directory when processing several files",
+                output.display()
+            )));
+        }
+
+        let path = output.join(&target.relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(Some(path))
+    }
+
+    /// Runs `job` on every file, up to `jobs` at a time, defaulting to one
+    /// per CPU.
+    ///
+    /// Each job writes its output to a buffer that is printed in one piece
+    /// when it finishes, so parallel jobs never interleave. A single file
+    /// reports its error as the command's own. A batch carries on past
+    /// failures, reports each one, prints a summary to stderr and fails with
+    /// [`Error::FilesFailed`] if any file did.
+    pub fn run<F>(&self, jobs: Option<NonZeroUsize>, job: F) -> Result<()>
+    where
+        F: Fn(&Target, &mut Vec<u8>) -> Result<()> + Sync,
+    {
+        if !self.is_batch {
+            return match self.targets.first() {
+                Some(target) => {
+                    let mut output = Vec::new();
+                    let result = job(target, &mut output);
+                    let printed = stdio::print(&output, self.output_to_stderr);
+                    result?;
+                    Ok(printed?)
+                }
+                None => Ok(()),
+            };
+        }
+
+        if self.targets.is_empty() {
+            return Err(Error::InvalidArgument("no PNG files found".to_string()));
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.map_or(0, NonZeroUsize::get))
+            .build()
+            .map_err(|err| Error::InvalidArgument(format!("cannot start workers: {}", err)))?;
+
+        let failed = AtomicUsize::new(0);
+        // Once printing fails, as when a pipe is closed, the remaining files
+        // are still processed but nothing more is printed
+        let print_error = OnceLock::new();
+        pool.install(|| {
+            self.targets.par_iter().for_each(|target| {
+                let mut output = Vec::new();
+                if self.headers {
+                    let header = format!("==> {} <==\n", target.path.display());
+                    output.extend_from_slice(header.as_bytes());
+                }
+                let header_length = output.len();
+                let result = job(target, &mut output);
+
+                if output.len() > header_length && print_error.get().is_none() {
+                    if let Err(err) = stdio::print(&output, self.output_to_stderr) {
+                        let _ = print_error.set(err);
+                    }
+                }
+
+                if let Err(err) = result {
+                    eprintln!("Error: {}: {}", target.path.display(), describe(&err));
+                    failed.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        });
+
+        let failed = failed.into_inner();
+        let total = self.targets.len();
+        // On stderr so the files' own output stays machine-readable
+        eprintln!(
+            "{} files: {} succeeded, {} failed",
+            total,
+            total - failed,
+            failed
+        );
+
+        if failed > 0 {
+            return Err(Error::FilesFailed { failed, total });
+        }
+
+        match print_error.into_inner() {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The error and its sources on one line, for reporting one failure among
+/// many files.
+pub fn describe(err: &Error) -> String {
+    let mut description = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        description.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+
+    description
+}
+
+fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+fn expand_pattern(pattern: &Path, recursive: bool, targets: &mut Vec<Target>) -> Result<()> {
+    let pattern = pattern.to_str().ok_or_else(|| {
+        Error::InvalidArgument(format!("pattern {} is not UTF-8", pattern.display()))
+    })?;
+    let paths = glob::glob(pattern)
+        .map_err(|err| Error::InvalidArgument(format!("invalid pattern '{}': {}", pattern, err)))?;
+
+    let found = targets.len();
+    for path in paths {
+        let path = path.map_err(std::io::Error::from)?;
+        if path.is_dir() {
+            if recursive {
+                walk(&path, path.parent().unwrap_or(&path), true, targets)?;
+            }
+        } else {
+            targets.push(Target {
+                relative: path.file_name().map(PathBuf::from).unwrap_or_default(),
+                path,
+            });
+        }
+    }
+
+    if targets.len() == found {
+        return Err(Error::InvalidArgument(format!("no files match '{}'", pattern)));
+    }
+
+    Ok(())
+}
+
+/// Adds the `.png` files in `directory`, in name order, with paths relative
+/// to `root`.
+fn walk(directory: &Path, root: &Path, recursive: bool, targets: &mut Vec<Target>) -> Result<()> {
+    let mut entries = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                walk(&path, root, recursive, targets)?;
+            }
+        } else if is_png(&path) {
+            targets.push(Target {
+                relative: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+                path,
+            });
+        }
+    }
+
+    Ok(())
+}
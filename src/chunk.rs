@@ -1,7 +1,15 @@
+use std::fmt;
+use crc::{Crc, CRC_32_ISO_HDLC};
 use crate::chunk_type::ChunkType;
 use crate::{Error, Result};
 
-struct Chunk {
+/// CRC-32 as used by PNG, over a chunk's type followed by its data.
+pub(crate) static CHUNK_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// A single PNG chunk: a length, a [`ChunkType`], the data and a CRC-32
+/// over the type and data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
     chunk_length: u32,
     chunk_type: ChunkType,
     chunk_data: Vec<u8>,
@@ -12,10 +20,145 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self> {
-        todo!()
+        let chunk_length = Chunk::declared_length(bytes)?;
+        let chunk_size = Chunk::METADATA_LENGTH + chunk_length as usize;
+        if bytes.len() < chunk_size {
+            return Err(Error::TruncatedChunk {
+                needed: chunk_size,
+                available: bytes.len(),
+            });
+        }
+
+        if bytes.len() > chunk_size {
+            return Err(Error::LengthMismatch {
+                declared: chunk_length,
+                actual: bytes.len() - Chunk::METADATA_LENGTH,
+            });
+        }
+
+        let type_bytes = [bytes[4], bytes[5], bytes[6], bytes[7]];
+        let chunk_type = ChunkType::try_from(type_bytes)?;
+
+        let (chunk_data, crc_bytes) = bytes[8..].split_at(chunk_length as usize);
+        let chunk_crc = read_u32(crc_bytes);
+
+        let chunk = Chunk::new(chunk_type, chunk_data.to_vec());
+        if chunk.crc() != chunk_crc {
+            return Err(Error::CrcMismatch {
+                expected: chunk.crc(),
+                found: chunk_crc,
+            });
+        }
+
+        Ok(chunk)
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chunk {{")?;
+        writeln!(f, "  Length: {}", self.length())?;
+        writeln!(f, "  Type: {}", self.chunk_type())?;
+        writeln!(f, "  Data: {} bytes", self.data().len())?;
+        writeln!(f, "  Crc: {}", self.crc())?;
+        write!(f, "}}")
+    }
+}
+
+impl Chunk {
+    /// Size of the length, type and CRC fields surrounding the chunk data.
+    pub const METADATA_LENGTH: usize = 12;
+
+    /// The PNG spec limits chunk data to 2^31 - 1 bytes.
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+    /// Reads the length field at the start of a serialized chunk, checking
+    /// that the surrounding metadata is present and the length is in range.
+    pub fn declared_length(bytes: &[u8]) -> Result<u32> {
+        if bytes.len() < Chunk::METADATA_LENGTH {
+            return Err(Error::TruncatedChunk {
+                needed: Chunk::METADATA_LENGTH,
+                available: bytes.len(),
+            });
+        }
+
+        let length = read_u32(bytes);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::ChunkTooLong(length));
+        }
+
+        Ok(length)
+    }
+
+    /// Creates a chunk, computing its length and CRC from the data.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than [`Chunk::MAX_LENGTH`], which the length
+    /// field could not represent. Callers that take data from users check
+    /// its length first.
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        assert!(
+            data.len() <= Chunk::MAX_LENGTH as usize,
+            "chunk data is {} bytes, over the limit of {}",
+            data.len(),
+            Chunk::MAX_LENGTH
+        );
+
+        let mut digest = CHUNK_CRC.digest();
+        digest.update(&chunk_type.bytes());
+        digest.update(&data);
+
+        Chunk {
+            chunk_length: data.len() as u32,
+            chunk_type,
+            chunk_data: data,
+            chunk_crc: digest.finalize(),
+        }
+    }
+
+    /// Number of data bytes, not counting the length, type and CRC fields.
+    pub fn length(&self) -> u32 {
+        self.chunk_length
+    }
+
+    /// The four-letter type of this chunk.
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// The raw chunk data.
+    pub fn data(&self) -> &[u8] {
+        &self.chunk_data
+    }
+
+    /// The CRC-32 (ISO-HDLC) of the chunk type followed by the data.
+    pub fn crc(&self) -> u32 {
+        self.chunk_crc
+    }
+
+    /// The chunk data decoded as UTF-8 text.
+    pub fn data_as_string(&self) -> Result<String> {
+        Ok(String::from_utf8(self.chunk_data.clone())?)
+    }
+
+    /// Serializes the chunk exactly as it appears in a PNG file.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.chunk_length
+            .to_be_bytes()
+            .iter()
+            .chain(self.chunk_type.bytes().iter())
+            .chain(self.chunk_data.iter())
+            .chain(self.chunk_crc.to_be_bytes().iter())
+            .copied()
+            .collect()
     }
 }
 
+pub(crate) fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -40,6 +183,15 @@ mod tests {
         Chunk::try_from(chunk_data.as_ref()).unwrap()
     }
 
+    #[test]
+    fn test_new_chunk() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = "This is where your secret message will be!".as_bytes().to_vec();
+        let chunk = Chunk::new(chunk_type, data);
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
@@ -114,6 +266,34 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_crc_mismatch_error() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let last = chunk_data.len() - 1;
+        chunk_data[last] ^= 1;
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert!(matches!(chunk, Err(Error::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_chunk_with_wrong_length() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[3] = 43;
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert!(matches!(chunk, Err(Error::TruncatedChunk { .. })));
+    }
+
+    #[test]
+    fn test_as_bytes_round_trip() {
+        let chunk_data = testing_chunk().as_bytes();
+        let chunk = Chunk::try_from(chunk_data.as_ref()).unwrap();
+        assert_eq!(chunk.as_bytes(), chunk_data);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -1,8 +1,11 @@
 use std::str::FromStr;
 use std::fmt;
-use crate::{Error, Result};
 
-#[derive(Debug, PartialEq, Eq)]
+use rand_core::{OsRng, RngCore};
+
+/// The four-letter type of a chunk. Bit 5 of each letter (its case) carries
+/// a property: ancillary, private, reserved and safe-to-copy, in order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ChunkType {
     ancillary_bit: u8,
     private_bit: u8,
@@ -20,10 +23,45 @@ impl fmt::Display for ChunkType {
     }
 }
 
+/// Why a sequence of bytes was rejected as a chunk type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkTypeError {
+    /// Chunk types are exactly four bytes long.
+    InvalidLength(usize),
+    /// The byte at `index` is not an ASCII letter (A-Z or a-z).
+    InvalidByte { index: usize, byte: u8 },
+    /// The third letter is lowercase, which the spec reserves. Only
+    /// [`ChunkType::check_valid`] returns this: the constructors accept such
+    /// types so that existing files can still be read.
+    ReservedBitSet,
+}
+
+impl fmt::Display for ChunkTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkTypeError::InvalidLength(length) => {
+                write!(f, "Chunk type must be 4 bytes long, found {}", length)
+            }
+            ChunkTypeError::InvalidByte { index, byte } => write!(
+                f,
+                "Chunk type byte {} is {:#04x}, expected an ASCII letter",
+                index, byte
+            ),
+            ChunkTypeError::ReservedBitSet => {
+                write!(f, "Chunk type's third letter must be uppercase")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChunkTypeError {}
+
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = Error;
+    type Error = ChunkTypeError;
+
+    fn try_from(value: [u8; 4]) -> std::result::Result<Self, ChunkTypeError> {
+        ChunkType::check_bytes(&value)?;
 
-    fn try_from(value: [u8; 4]) -> Result<Self> {
         let chunk = ChunkType {
             ancillary_bit: value[0],
             private_bit: value[1],
@@ -36,28 +74,64 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl FromStr for ChunkType {
-    type Err = Error;
-
-    fn from_str(string: &str) -> Result<Self> {
-        if string.chars().all(char::is_alphabetic) {
-            let mut chars = string.chars();
-
-            let chunk = ChunkType {
-                ancillary_bit: chars.next().unwrap() as u8,
-                private_bit: chars.next().unwrap() as u8,
-                reserved_bit: chars.next().unwrap() as u8,
-                safe_to_copy_bit: chars.next().unwrap() as u8,
-            };
-            
-            Ok(chunk)
-        } else {
-            Err("Chunk must be alphabetic")?
-        }
+    type Err = ChunkTypeError;
+
+    fn from_str(string: &str) -> std::result::Result<Self, ChunkTypeError> {
+        let bytes = string.as_bytes();
+        ChunkType::check_bytes(bytes)?;
+
+        let bytes: [u8; 4] = bytes
+            .try_into()
+            .map_err(|_| ChunkTypeError::InvalidLength(bytes.len()))?;
+
+        ChunkType::try_from(bytes)
     }
 }
 
 impl ChunkType {
-    fn bytes(&self) -> [u8; 4] {
+    /// Rejects anything that is not exactly four ASCII letters, reporting
+    /// the first offending byte before the length.
+    fn check_bytes(bytes: &[u8]) -> std::result::Result<(), ChunkTypeError> {
+        if let Some(index) = bytes.iter().position(|byte| !byte.is_ascii_alphabetic()) {
+            return Err(ChunkTypeError::InvalidByte {
+                index,
+                byte: bytes[index],
+            });
+        }
+
+        if bytes.len() != 4 {
+            return Err(ChunkTypeError::InvalidLength(bytes.len()));
+        }
+
+        Ok(())
+    }
+
+    /// A random type that looks like any other private chunk: ancillary,
+    /// private and safe to copy.
+    pub fn random() -> ChunkType {
+        let mut seed = [0; 4];
+        OsRng.fill_bytes(&mut seed);
+        ChunkType::inconspicuous(seed)
+    }
+
+    /// Maps each seed byte to a letter, with the cases that make the type
+    /// ancillary, private, safe to copy and valid.
+    pub(crate) fn inconspicuous(seed: [u8; 4]) -> ChunkType {
+        let mut letters = seed.map(|byte| b'a' + byte % 26);
+        letters[2] = letters[2].to_ascii_uppercase();
+
+        let chunk_type = ChunkType::try_from(letters).expect("seed maps to ASCII letters");
+        debug_assert!(
+            chunk_type.is_valid()
+                && !chunk_type.is_critical()
+                && !chunk_type.is_public()
+                && chunk_type.is_safe_to_copy()
+        );
+        chunk_type
+    }
+
+    /// The four ASCII bytes of the type.
+    pub fn bytes(&self) -> [u8; 4] {
         [
             self.ancillary_bit,
             self.private_bit,
@@ -66,28 +140,46 @@ impl ChunkType {
         ]
     }
 
-    fn is_valid(&self) -> bool {
+    /// Whether the type may appear in a PNG file written today.
+    pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
 
-    fn is_safe_to_copy(&self) -> bool {
+    /// Like [`ChunkType::is_valid`], but with the reason, for types about to
+    /// be written.
+    pub fn check_valid(&self) -> std::result::Result<(), ChunkTypeError> {
+        if !self.is_reserved_bit_valid() {
+            return Err(ChunkTypeError::ReservedBitSet);
+        }
+
+        Ok(())
+    }
+
+    /// Lowercase fourth letter: editors that don't understand the chunk may
+    /// still copy it when they modify critical chunks.
+    pub fn is_safe_to_copy(&self) -> bool {
         let ch = self.safe_to_copy_bit as char;
-        ch.is_lowercase()
+        ch.is_ascii_lowercase()
     }
 
-    fn is_public(&self) -> bool {
+    /// Uppercase second letter: the type is defined by the PNG spec or a
+    /// registered extension.
+    pub fn is_public(&self) -> bool {
         let ch = self.private_bit as char;
-        ch.is_uppercase()
+        ch.is_ascii_uppercase()
     }
 
-    fn is_critical(&self) -> bool {
+    /// Uppercase first letter: decoders must understand the chunk to
+    /// display the image.
+    pub fn is_critical(&self) -> bool {
         let ch = self.ancillary_bit as char;
-        ch.is_uppercase()
+        ch.is_ascii_uppercase()
     }
 
-    fn is_reserved_bit_valid(&self) -> bool {
+    /// Uppercase third letter, as the current spec requires.
+    pub fn is_reserved_bit_valid(&self) -> bool {
         let ch = self.reserved_bit as char;
-        ch.is_uppercase() && ch.is_alphabetic()
+        ch.is_ascii_uppercase()
     }
 }
 
@@ -170,11 +262,41 @@ mod tests {
     pub fn test_invalid_chunk_is_valid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
         assert!(!chunk.is_valid());
+        assert_eq!(chunk.check_valid(), Err(ChunkTypeError::ReservedBitSet));
+        assert_eq!(ChunkType::from_str("RuSt").unwrap().check_valid(), Ok(()));
 
         let chunk = ChunkType::from_str("Ru1t");
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_wrong_length() {
+        assert_eq!(
+            ChunkType::from_str("Ru"),
+            Err(ChunkTypeError::InvalidLength(2))
+        );
+        assert_eq!(
+            ChunkType::from_str("RuStX"),
+            Err(ChunkTypeError::InvalidLength(5))
+        );
+    }
+
+    #[test]
+    pub fn test_chunk_type_non_ascii() {
+        assert_eq!(
+            ChunkType::from_str("Ru\u{e9}t"),
+            Err(ChunkTypeError::InvalidByte { index: 2, byte: 0xc3 })
+        );
+    }
+
+    #[test]
+    pub fn test_chunk_type_invalid_bytes() {
+        assert_eq!(
+            ChunkType::try_from([82, 117, 0, 116]),
+            Err(ChunkTypeError::InvalidByte { index: 2, byte: 0 })
+        );
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -188,4 +310,17 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_generated_chunk_types() {
+        let inconspicuous = |chunk_type: &ChunkType| {
+            chunk_type.is_valid()
+                && !chunk_type.is_critical()
+                && !chunk_type.is_public()
+                && chunk_type.is_safe_to_copy()
+        };
+
+        assert!((0..100).map(|_| ChunkType::random()).all(|chunk_type| inconspicuous(&chunk_type)));
+        assert!((0..=255).all(|byte| inconspicuous(&ChunkType::inconspicuous([byte; 4]))));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,1521 @@
+use std::fs::{self, File, FileTimes};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use png_secret::attachment::{Attachment, Metadata};
+use png_secret::integrity::{self, Digest, Manifest};
+use png_secret::report::{ChunkInfo, Data, TrailerInfo};
+use png_secret::scan::{self, Report};
+use png_secret::signature::{self, KeyId, Scope, Signature};
+use png_secret::split::{self, Piece};
+use png_secret::text::{self, TextChunk};
+use png_secret::validate::{self, Finding, Severity};
+use png_secret::payload::{self, Envelope, Kind};
+use png_secret::{compress, crypto, lsb};
+use png_secret::{Chunk, ChunkReader, ChunkType, ChunkWriter, Error, Ihdr, Png, Result};
+
+use crate::batch::{Batch, Target};
+use crate::stdio;
+use crate::args::{
+    AddIntegrityArgs, CheckIntegrityArgs, DecodeArgs, EncodeArgs, Format, KeygenArgs, Method,
+    PrintArgs, RemoveArgs, ScanArgs, SecretArgs, SignArgs, TextArgs, TextCommand, TextSetArgs,
+    TrailerArgs, TrailerCommand, TrailerWriteArgs, ValidateArgs, VerifyArgs,
+};
+
+/// This crate's own chunks that are unsafe to copy. They are kept when the
+/// critical chunks change so that `check-integrity` and `verify` report the
+/// change rather than finding nothing to check.
+const OWN_UNSAFE_TO_COPY: [&str; 2] = [integrity::CHUNK_TYPE, signature::CHUNK_TYPE];
+
+type InputReader = ChunkReader<BufReader<Box<dyn Read>>>;
+type OutputWriter = ChunkWriter<BufWriter<Box<dyn Write>>>;
+
+fn open_png(path: &Path) -> Result<InputReader> {
+    ChunkReader::new(BufReader::new(stdio::open(path)?))
+}
+
+fn read_png(path: &Path) -> Result<Png> {
+    let bytes = stdio::read(path)?;
+    Png::try_from(bytes.as_ref())
+}
+
+/// Whether editing `input` writes the PNG to standard output: when asked
+/// to, and when the input is standard input and there is no output, since
+/// it cannot be edited in place.
+fn writes_to_stdout(input: &Path, output: Option<&Path>) -> bool {
+    output.map_or_else(|| stdio::is_stdio(input), stdio::is_stdio)
+}
+
+/// Fails if standard input would be read for more than one thing.
+fn check_stdin(uses: &[bool]) -> Result<()> {
+    if uses.iter().filter(|&&used| used).count() > 1 {
+        return Err(Error::InvalidArgument(
+            "standard input can only be read for one of the inputs".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Prints a status line for a command that edits a single file, on stderr
+/// when the edited PNG goes to stdout.
+fn print_status(message: &str, to_stderr: bool) -> Result<()> {
+    stdio::print(format!("{}\n", message).as_bytes(), to_stderr)?;
+    Ok(())
+}
+
+/// Writes `value` as a JSON or YAML document. A batch prints compact JSON,
+/// one document per line, and starts each YAML document with `---`.
+fn write_document<T: Serialize>(
+    out: &mut Vec<u8>,
+    format: Format,
+    value: &T,
+    batch: bool,
+) -> Result<()> {
+    match format {
+        Format::Json if batch => serde_json::to_writer(&mut *out, value).map_err(io::Error::from)?,
+        Format::Json => serde_json::to_writer_pretty(&mut *out, value).map_err(io::Error::from)?,
+        Format::Yaml => {
+            if batch {
+                out.extend_from_slice(b"---\n");
+            }
+            // YAML output already ends with a newline
+            serde_yaml::to_writer(&mut *out, value).map_err(io::Error::other)?;
+            return Ok(());
+        }
+        Format::Text => unreachable!("text output is written by each command"),
+    }
+
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Prints `value` as a single JSON or YAML document.
+fn print_document<T: Serialize>(format: Format, value: &T) -> Result<()> {
+    let mut out = Vec::new();
+    write_document(&mut out, format, value, false)?;
+    io::stdout().write_all(&out)?;
+    Ok(())
+}
+
+/// Streams `input` through `edit` into `output`. Without an output, or when
+/// it names the input, the input is replaced with [`replace_file`], keeping
+/// a copy named with the `backup` suffix if one is given. Standard input
+/// goes to standard output unless there is an output.
+fn rewrite<F>(input: &Path, output: Option<&Path>, backup: Option<&str>, edit: F) -> Result<()>
+where
+    F: FnOnce(&mut InputReader, &mut OutputWriter) -> Result<()>,
+{
+    let mut reader = open_png(input)?;
+    let write = |file: Box<dyn Write>| {
+        let mut writer = ChunkWriter::new(BufWriter::new(file))?;
+        edit(&mut reader, &mut writer)?;
+        // Keep data after IEND unless the edit already dealt with it
+        writer.copy_trailer(&mut reader)?;
+        writer.finish()?;
+        Ok(())
+    };
+
+    match output {
+        _ if writes_to_stdout(input, output) => write(Box::new(io::stdout())),
+        Some(output) if !is_same_file(input, output) => create_output(output, write),
+        _ => replace_file(input, backup, |file| write(Box::new(file))),
+    }
+}
+
+/// Writes `bytes` to `output`, or replaces `input` with them through
+/// [`replace_file`] when there is no output or it names the input.
+fn write_png(
+    input: &Path,
+    output: Option<&Path>,
+    backup: Option<&str>,
+    bytes: &[u8],
+) -> Result<()> {
+    match output {
+        _ if writes_to_stdout(input, output) => Ok(io::stdout().write_all(bytes)?),
+        Some(output) if !is_same_file(input, output) => {
+            create_output(output, |mut file| Ok(file.write_all(bytes)?))
+        }
+        _ => replace_file(input, backup, |mut file| Ok(file.write_all(bytes)?)),
+    }
+}
+
+/// Creates `output` and writes it with `write`, removing it again if that
+/// fails so no truncated PNG is left behind.
+fn create_output<F>(output: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(Box<dyn Write>) -> Result<()>,
+{
+    let result = stdio::create(output).map_err(Error::from).and_then(write);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+/// Replaces `path` with what `write` writes, so that an interruption leaves
+/// either the old file or the new one but never a mix.
+///
+/// The new contents go to a temporary file in the same directory, which
+/// gets the original's permissions and timestamps, is synced to disk and is
+/// then renamed over the original. With a `backup` suffix, the original is
+/// kept under its name plus the suffix.
+fn replace_file<F>(path: &Path, backup: Option<&str>, write: F) -> Result<()>
+where
+    F: FnOnce(File) -> Result<()>,
+{
+    let metadata = fs::metadata(path)?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // Removed again if anything fails before it is renamed
+    let temp = tempfile::Builder::new()
+        .prefix(".png-secret")
+        .suffix(".tmp")
+        .tempfile_in(directory)?;
+    write(temp.as_file().try_clone()?)?;
+
+    let file = temp.as_file();
+    file.set_permissions(metadata.permissions())?;
+    file.set_times(
+        FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?),
+    )?;
+    file.sync_all()?;
+
+    if let Some(suffix) = backup {
+        make_backup(path, suffix)?;
+    }
+
+    temp.persist(path).map_err(|err| err.error)?;
+    sync_directory(directory);
+    Ok(())
+}
+
+/// Keeps the current contents of `path` under its name plus `suffix`,
+/// replacing any earlier backup.
+fn make_backup(path: &Path, suffix: &str) -> Result<()> {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    let backup = PathBuf::from(name);
+
+    match fs::remove_file(&backup) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+
+    // A hard link keeps the original file itself once the new one is
+    // renamed over its name; copy where links are not supported
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+
+    Ok(())
+}
+
+/// Makes a rename in `directory` durable. Best effort: the file has already
+/// been replaced, so failing here would only misreport the outcome.
+fn sync_directory(directory: &Path) {
+    #[cfg(unix)]
+    if let Ok(directory) = File::open(directory) {
+        let _ = directory.sync_all();
+    }
+
+    #[cfg(not(unix))]
+    let _ = directory;
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The passphrase or key file contents, if either was given.
+fn read_secret(args: &SecretArgs) -> Result<Option<Vec<u8>>> {
+    match (&args.passphrase, &args.key_file) {
+        (Some(passphrase), _) => Ok(Some(passphrase.as_bytes().to_vec())),
+        (None, Some(path)) => Ok(Some(stdio::read(path)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// The `chunk_type` argument of encode and decode: a four-letter type, or
+/// `auto` for one that is inconspicuous. That is derived from the secret
+/// when there is one, so decode can find it again, and otherwise random
+/// if `random` allows it.
+fn chunk_type_arg(name: &str, secret: Option<&[u8]>, random: bool) -> Result<ChunkType> {
+    match (name, secret) {
+        ("auto", Some(secret)) => crypto::derive_chunk_type(secret),
+        ("auto", None) if random => Ok(ChunkType::random()),
+        ("auto", None) => Err(Error::InvalidArgument(
+            "chunk type auto is derived from --passphrase or --key-file; without either, \
+             give the chunk type encode printed"
+                .to_string(),
+        )),
+        (name, _) => Ok(ChunkType::from_str(name)?),
+    }
+}
+
+pub fn encode(args: EncodeArgs) -> Result<()> {
+    if args.method.method == Method::Lsb && args.max_chunk_size.is_some() {
+        return Err(Error::InvalidArgument(
+            "--max-chunk-size only applies to the chunk method".to_string(),
+        ));
+    }
+
+    check_stdin(&[
+        stdio::is_stdio(&args.file),
+        args.message_stdin || args.message_file.as_deref().is_some_and(stdio::is_stdio),
+        args.attach.as_deref().is_some_and(stdio::is_stdio),
+        args.secret.key_file.as_deref().is_some_and(stdio::is_stdio),
+    ])?;
+    let secret = read_secret(&args.secret)?;
+
+    let chunk_type = chunk_type_arg(&args.chunk_type, secret.as_deref(), true)?;
+    chunk_type.check_valid()?;
+
+    let (message, output) = read_message(&args)?;
+    // Compress before encrypting; ciphertext does not compress
+    let data = compress::compress(&message, args.compress)?;
+    let kind = match args.attach {
+        Some(_) => Kind::Attachment,
+        None => Kind::Message,
+    };
+
+    let mut batch = Batch::expand(std::slice::from_ref(&args.file), args.batch.recursive)?;
+    batch.check_output(output.as_deref())?;
+    batch.output_to_stderr = writes_to_stdout(&args.file, output.as_deref());
+    batch.run(args.batch.jobs, |target, out| {
+        // Encrypted per file so each gets its own salt and nonce
+        let data = match &secret {
+            Some(secret) => {
+                let envelope = Envelope {
+                    encrypted: true,
+                    kind,
+                };
+                payload::wrap(envelope, &crypto::encrypt(&data, secret)?)
+            }
+            None => payload::wrap(Envelope { encrypted: false, kind }, &data),
+        };
+        let output = batch.output_path(target, output.as_deref())?;
+        if args.chunk_type == "auto" {
+            writeln!(out, "Using chunk type {}", chunk_type)?;
+        }
+        encode_file(&args, &chunk_type, &data, &target.path, output.as_deref(), out)
+    })
+}
+
+/// The message to encode and the output path. A message read from a file
+/// or stdin leaves the positional message argument to name the output.
+fn read_message(args: &EncodeArgs) -> Result<(Vec<u8>, Option<PathBuf>)> {
+    let message = match (&args.attach, &args.message_file, args.message_stdin) {
+        (Some(path), _, _) => {
+            // Standard input has no name to restore
+            let name = match path.file_name() {
+                Some(name) if !stdio::is_stdio(path) => name.to_string_lossy().into_owned(),
+                _ => String::new(),
+            };
+            let mut attachment = Attachment::new(&name, stdio::read(path)?);
+            if let Some(mime_type) = &args.mime_type {
+                attachment.mime_type = mime_type.clone();
+            }
+
+            attachment.to_bytes()?
+        }
+        (None, Some(path), _) => stdio::read(path)?,
+        (None, None, true) => stdio::read(Path::new("-"))?,
+        (None, None, false) => {
+            // Clap requires a message unless it is read from elsewhere
+            let message = args.message.clone().unwrap_or_default();
+            return Ok((message.into_bytes(), args.output.clone()));
+        }
+    };
+
+    match (&args.message, &args.output) {
+        (Some(_), Some(_)) => Err(Error::InvalidArgument(
+            "a message argument cannot be combined with --file, --message-file or \
+             --message-stdin"
+                .to_string(),
+        )),
+        (output, _) => Ok((message, output.as_ref().map(PathBuf::from))),
+    }
+}
+
+fn encode_file(
+    args: &EncodeArgs,
+    chunk_type: &ChunkType,
+    data: &[u8],
+    input: &Path,
+    output: Option<&Path>,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    match args.method.method {
+        Method::Chunk => {
+            // Chunks cannot hold more than 2^31 - 1 bytes, so larger
+            // messages are always split
+            let max_chunk_size = match args.max_chunk_size {
+                Some(size) => Some(size),
+                None if data.len() > Chunk::MAX_LENGTH as usize => {
+                    Some(Chunk::MAX_LENGTH as usize)
+                }
+                None => None,
+            };
+
+            let chunks = match max_chunk_size {
+                Some(size) => {
+                    let payload_id = args.payload_id.unwrap_or_else(split::new_payload_id);
+                    let size = size.min(Chunk::MAX_LENGTH as usize);
+                    let pieces = split::split(data, size, payload_id)?;
+                    writeln!(
+                        out,
+                        "Split payload {:016x} into {} chunks",
+                        payload_id,
+                        pieces.len()
+                    )?;
+
+                    pieces
+                        .iter()
+                        .map(|piece| Chunk::new(chunk_type.clone(), piece.to_bytes()))
+                        .collect()
+                }
+                None => vec![Chunk::new(chunk_type.clone(), data.to_vec())],
+            };
+
+            // Adding a critical chunk changes what decoders must understand
+            let drop_unsafe = chunk_type.is_critical() && !args.keep_unsafe;
+            let backup = args.backup.as_deref();
+            let dropped = encode_chunks(input, output, backup, &chunks, drop_unsafe)?;
+            print_dropped(out, &dropped)
+        }
+        Method::Lsb => {
+            let png = read_png(input)?;
+            let channels = args.method.channels;
+
+            let capacity = lsb::capacity(&png, channels)?;
+            writeln!(
+                out,
+                "LSB capacity in channels {}: {} bytes, payload: {} bytes",
+                channels,
+                capacity,
+                data.len()
+            )?;
+
+            let mut png = lsb::embed(&png, chunk_type, data, channels)?;
+            let dropped = if args.keep_unsafe {
+                Vec::new()
+            } else {
+                png.drop_unsafe_to_copy(&OWN_UNSAFE_TO_COPY)
+            };
+            write_png(input, output, args.backup.as_deref(), &png.as_bytes())?;
+
+            let dropped: Vec<String> =
+                dropped.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+            print_dropped(out, &dropped)
+        }
+    }
+}
+
+fn encode_chunks(
+    input: &Path,
+    output: Option<&Path>,
+    backup: Option<&str>,
+    chunks: &[Chunk],
+    drop_unsafe: bool,
+) -> Result<Vec<String>> {
+    let mut dropped = Vec::new();
+    rewrite(input, output, backup, |reader, writer| {
+        let mut inserted = false;
+        while let Some(header) = reader.next_header()? {
+            if drop_unsafe && Png::must_drop_on_edit(&header.chunk_type, &OWN_UNSAFE_TO_COPY) {
+                // Skipped when the next header is read
+                dropped.push(header.chunk_type.to_string());
+                continue;
+            }
+
+            // Keep IEND last so other decoders still read the image
+            if !inserted && header.chunk_type.to_string() == "IEND" {
+                chunks.iter().try_for_each(|chunk| writer.write_chunk(chunk))?;
+                inserted = true;
+            }
+
+            writer.copy_chunk(&header, reader)?;
+        }
+
+        if !inserted {
+            chunks.iter().try_for_each(|chunk| writer.write_chunk(chunk))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(dropped)
+}
+
+/// Reports the chunks dropped by [`Png::drop_unsafe_to_copy`] or its
+/// streaming equivalent.
+fn print_dropped(out: &mut impl Write, dropped: &[String]) -> Result<()> {
+    if !dropped.is_empty() {
+        writeln!(
+            out,
+            "Dropped {} chunk(s) unsafe to copy after changing critical chunks: {} \
+             (keep them with --keep-unsafe)",
+            dropped.len(),
+            dropped.join(", ")
+        )?;
+    }
+
+    Ok(())
+}
+
+/// A decoded message, for `decode --format json` or `yaml`.
+#[derive(Serialize)]
+struct DecodeReport<'a> {
+    file: &'a Path,
+    chunk_type: &'a str,
+    /// The message, or the contents of a file hidden with `encode --file`.
+    message: Data,
+    /// The name, MIME type and size of a file hidden with `encode --file`,
+    /// or null for other messages.
+    attachment: Option<Metadata>,
+    /// Where `--output` wrote the message, or null.
+    output: Option<PathBuf>,
+    /// The chunks the message was read from; empty for `--method lsb`.
+    chunks: Vec<ChunkInfo>,
+}
+
+pub fn decode(args: DecodeArgs) -> Result<()> {
+    check_stdin(&[
+        stdio::is_stdio(&args.file),
+        args.secret.key_file.as_deref().is_some_and(stdio::is_stdio),
+    ])?;
+    let secret = read_secret(&args.secret)?;
+    let chunk_type = chunk_type_arg(&args.chunk_type, secret.as_deref(), false)?.to_string();
+    let format = args.format.format;
+
+    let mut batch = Batch::expand(std::slice::from_ref(&args.file), args.batch.recursive)?;
+    batch.check_output(args.output.as_deref())?;
+    batch.headers = format == Format::Text;
+    // With --output -, the message is written to stdout as is
+    let raw = args.output.as_deref().is_some_and(stdio::is_stdio);
+    let output = args.output.as_deref().filter(|output| !stdio::is_stdio(output));
+
+    batch.run(args.batch.jobs, |target, out| {
+        let (kind, message, chunks) =
+            decode_file(&args, &chunk_type, secret.as_deref(), &target.path)?;
+        let attachment = match kind {
+            Kind::Attachment => Some(Attachment::try_from(message.as_ref())?),
+            Kind::Message => None,
+        };
+        let data = attachment.as_ref().map_or(&message, |attachment| &attachment.data);
+
+        let path = match output {
+            Some(output) => {
+                let path = message_path(&batch, target, output, attachment.as_ref())?;
+                write_message(&path, data, args.force)?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        match (format, &path) {
+            (Format::Text, Some(path)) => {
+                writeln!(out, "Wrote {} bytes to {}", data.len(), path.display())?;
+            }
+            (Format::Text, None) => match std::str::from_utf8(data) {
+                Ok(text) if !raw && attachment.is_none() => writeln!(out, "{}", text)?,
+                // Files and binary messages are written as they are
+                _ => out.extend_from_slice(data),
+            },
+            (format, _) => {
+                let report = DecodeReport {
+                    file: &target.path,
+                    chunk_type: &chunk_type,
+                    message: Data::new(data),
+                    attachment: attachment.as_ref().map(Attachment::metadata),
+                    output: path,
+                    chunks,
+                };
+                write_document(out, format, &report, batch.is_batch)?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Where `decode --output` writes the message from `target`. When `output`
+/// is a directory, the message goes in it under the name stored with a
+/// hidden file, or else the image's name with a `.bin` extension. A batch
+/// gives each image its own directory, named after the image and laid out
+/// like the inputs, so that files stored under the same name don't clash.
+fn message_path(
+    batch: &Batch,
+    target: &Target,
+    output: &Path,
+    attachment: Option<&Attachment>,
+) -> Result<PathBuf> {
+    let directory = if batch.is_batch {
+        let mirrored = batch.output_path(target, Some(output))?.unwrap_or_default();
+        let directory = mirrored.with_extension("");
+        fs::create_dir_all(&directory)?;
+        directory
+    } else if output.is_dir() {
+        output.to_path_buf()
+    } else {
+        return Ok(output.to_path_buf());
+    };
+
+    let path = match attachment {
+        Some(attachment) if !attachment.file_name.is_empty() => {
+            directory.join(&attachment.file_name)
+        }
+        _ => {
+            let name = target.relative.with_extension("bin");
+            directory.join(name.file_name().unwrap_or_default())
+        }
+    };
+
+    if is_same_file(&path, &target.path) {
+        return Err(Error::InvalidArgument(format!(
+            "writing the message to {} would overwrite the image",
+            path.display()
+        )));
+    }
+
+    Ok(path)
+}
+
+/// Writes a decoded message to `path`. Its name may come from the image, so
+/// a file already there is only replaced with `--force`.
+fn write_message(path: &Path, data: &[u8], force: bool) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    if force {
+        options.write(true).create(true).truncate(true);
+    } else {
+        options.write(true).create_new(true);
+    }
+
+    let mut file = options.open(path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => Error::InvalidArgument(format!(
+            "{} already exists; pass --force to replace it",
+            path.display()
+        )),
+        _ => Error::from(err),
+    })?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+/// What kind of message was hidden, the decrypted, decompressed message,
+/// and the chunks it came from.
+fn decode_file(
+    args: &DecodeArgs,
+    chunk_type: &str,
+    secret: Option<&[u8]>,
+    path: &Path,
+) -> Result<(Kind, Vec<u8>, Vec<ChunkInfo>)> {
+    let (data, chunks) = match args.method.method {
+        Method::Chunk => decode_chunks(path, chunk_type, args.payload_id)?,
+        Method::Lsb => {
+            let chunk_type = ChunkType::from_str(chunk_type)?;
+            let data = lsb::extract(&read_png(path)?, &chunk_type, args.method.channels)?;
+            (data, Vec::new())
+        }
+    };
+
+    let (envelope, body) = payload::unwrap(&data)?;
+    let message = match (envelope.encrypted, secret) {
+        (true, Some(secret)) => crypto::decrypt(body, secret)?,
+        (true, None) => return Err(Error::PassphraseRequired),
+        (false, _) => body.to_vec(),
+    };
+
+    // Every message has a compression header, even when stored as is
+    let message = compress::decompress(&message, args.max_decompressed_size)?;
+    Ok((envelope.kind, message, chunks))
+}
+
+/// The payload in the first chunk of the given type that holds a whole one,
+/// or the one reassembled from split pieces in chunks of that type, with
+/// the chunks it was read from.
+fn decode_chunks(
+    path: &Path,
+    chunk_type: &str,
+    payload_id: Option<u64>,
+) -> Result<(Vec<u8>, Vec<ChunkInfo>)> {
+    let mut reader = open_png(path)?;
+    let mut offset = Png::STANDARD_HEADER.len() as u64;
+    let mut plain = None;
+    let mut pieces = Vec::new();
+    let mut piece_chunks = Vec::new();
+
+    while let Some(header) = reader.next_header()? {
+        let chunk_offset = offset;
+        offset += (Chunk::METADATA_LENGTH + header.length as usize) as u64;
+        if header.chunk_type.to_string() != chunk_type {
+            continue;
+        }
+
+        // Whole payloads start with their envelope and pieces with their
+        // own header, so the message itself is never looked at here
+        let chunk = Chunk::new(header.chunk_type, reader.read_data()?);
+        if payload::is_envelope(chunk.data()) {
+            if plain.is_none() {
+                plain = Some((ChunkInfo::new(&chunk, chunk_offset), chunk));
+            }
+        } else if split::is_piece(chunk.data()) {
+            let piece = Piece::try_from(chunk.data())?;
+            if payload_id.is_none_or(|id| id == piece.payload_id) {
+                pieces.push(piece);
+                piece_chunks.push(ChunkInfo::new(&chunk, chunk_offset));
+            }
+        } else {
+            return Err(Error::InvalidPayload(format!(
+                "{} chunk at offset {} holds no png-secret payload",
+                chunk_type, chunk_offset
+            )));
+        }
+    }
+
+    match (plain, pieces.is_empty(), payload_id) {
+        (Some((info, chunk)), true, None) => return Ok((chunk.data().to_vec(), vec![info])),
+        (None, true, None) => return Err(Error::ChunkNotFound(chunk_type.to_string())),
+        (_, true, Some(id)) => {
+            return Err(Error::InvalidArgument(format!(
+                "no payload {:016x} in {} chunks",
+                id, chunk_type
+            )))
+        }
+        (Some(_), false, None) => {
+            return Err(Error::InvalidArgument(format!(
+                "{} chunks hold both a plain and a split message; choose one with --payload-id",
+                chunk_type
+            )))
+        }
+        _ => {}
+    }
+
+    let payloads = split::reassemble(pieces)?;
+    if payloads.len() > 1 {
+        let ids: Vec<String> = payloads.keys().map(|id| format!("{:016x}", id)).collect();
+        return Err(Error::InvalidArgument(format!(
+            "{} chunks hold payloads {}; choose one with --payload-id",
+            chunk_type,
+            ids.join(", ")
+        )));
+    }
+
+    Ok((payloads.into_values().next().unwrap_or_default(), piece_chunks))
+}
+
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    let mut batch = Batch::expand(std::slice::from_ref(&args.file), args.batch.recursive)?;
+    batch.output_to_stderr = stdio::is_stdio(&args.file);
+    // Removing a critical chunk changes what decoders must understand
+    let drop_unsafe = !args.keep_unsafe
+        && ChunkType::from_str(&args.chunk_type).is_ok_and(|chunk_type| chunk_type.is_critical());
+
+    batch.run(args.batch.jobs, |target, out| {
+        let dropped =
+            remove_file(&target.path, &args.chunk_type, args.backup.as_deref(), drop_unsafe)?;
+        writeln!(out, "Removed chunk {}", args.chunk_type)?;
+        print_dropped(out, &dropped)
+    })
+}
+
+fn remove_file(
+    path: &Path,
+    chunk_type: &str,
+    backup: Option<&str>,
+    drop_unsafe: bool,
+) -> Result<Vec<String>> {
+    let mut dropped = Vec::new();
+    rewrite(path, None, backup, |reader, writer| {
+        let mut removed = false;
+        while let Some(header) = reader.next_header()? {
+            if drop_unsafe && Png::must_drop_on_edit(&header.chunk_type, &OWN_UNSAFE_TO_COPY) {
+                dropped.push(header.chunk_type.to_string());
+                continue;
+            }
+
+            if !removed && header.chunk_type.to_string() == chunk_type {
+                // Skipped when the next header is read
+                removed = true;
+                continue;
+            }
+
+            writer.copy_chunk(&header, reader)?;
+        }
+
+        if removed {
+            Ok(())
+        } else {
+            Err(Error::ChunkNotFound(chunk_type.to_string()))
+        }
+    })?;
+
+    Ok(dropped)
+}
+
+/// Every chunk in a file, for `print --format json` or `yaml`.
+#[derive(Serialize)]
+struct PrintReport<'a> {
+    file: &'a Path,
+    chunks: Vec<ChunkInfo>,
+    /// Data after `IEND`, or null if there is none.
+    trailer: Option<TrailerInfo>,
+}
+
+pub fn print(args: PrintArgs) -> Result<()> {
+    let format = args.format.format;
+    let mut batch = Batch::expand(&args.files, args.batch.recursive)?;
+    batch.headers = format == Format::Text;
+
+    batch.run(args.batch.jobs, |target, out| match format {
+        Format::Text => print_file(&target.path, out),
+        format => {
+            let report = print_report(&target.path)?;
+            write_document(out, format, &report, batch.is_batch)
+        }
+    })
+}
+
+fn print_report(path: &Path) -> Result<PrintReport<'_>> {
+    let mut reader = open_png(path)?;
+    let mut offset = Png::STANDARD_HEADER.len() as u64;
+    let mut chunks = Vec::new();
+    for chunk in reader.by_ref() {
+        let info = ChunkInfo::new(&chunk?, offset);
+        offset = info.next_offset();
+        chunks.push(info);
+    }
+
+    let trailer = reader.read_trailer()?;
+    Ok(PrintReport {
+        file: path,
+        chunks,
+        trailer: (!trailer.is_empty()).then(|| TrailerInfo::new(&trailer, offset)),
+    })
+}
+
+fn print_file(path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let mut reader = open_png(path)?;
+    for chunk in reader.by_ref() {
+        let chunk = chunk?;
+        writeln!(out, "{}", chunk)?;
+
+        if chunk.chunk_type().bytes() == *b"IHDR" {
+            match Ihdr::try_from(&chunk) {
+                Ok(ihdr) => writeln!(out, "{}", ihdr)?,
+                Err(err) => writeln!(out, "Invalid IHDR: {}", err)?,
+            }
+        }
+    }
+
+    let trailer = reader.copy_trailer(&mut io::sink())?;
+    if trailer > 0 {
+        writeln!(out, "{} bytes of data after IEND", trailer)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ValidationReport<'a> {
+    file: &'a Path,
+    valid: bool,
+    findings: &'a [Finding],
+}
+
+pub fn validate(args: ValidateArgs) -> Result<()> {
+    let format = if args.json { Format::Json } else { args.format.format };
+    let mut batch = Batch::expand(&args.files, args.batch.recursive)?;
+    // Reports name their file
+    batch.headers = format == Format::Text;
+
+    batch.run(args.batch.jobs, |target, out| {
+        validate_file(&target.path, format, batch.is_batch, out)
+    })
+}
+
+fn validate_file(path: &Path, format: Format, batch: bool, out: &mut Vec<u8>) -> Result<()> {
+    let findings = validate::validate(&stdio::read(path)?);
+    let count = |severity| {
+        findings
+            .iter()
+            .filter(|finding: &&Finding| finding.severity == severity)
+            .count()
+    };
+    let errors = count(Severity::Error);
+
+    if format != Format::Text {
+        let report = ValidationReport {
+            file: path,
+            valid: errors == 0,
+            findings: &findings,
+        };
+        write_document(out, format, &report, batch)?;
+    } else {
+        for finding in &findings {
+            writeln!(out, "{}", finding)?;
+        }
+        writeln!(
+            out,
+            "{}: {} error(s), {} warning(s)",
+            path.display(),
+            errors,
+            count(Severity::Warning)
+        )?;
+    }
+
+    if errors > 0 {
+        return Err(Error::ValidationFailed { errors });
+    }
+
+    Ok(())
+}
+
+pub fn text(args: TextArgs) -> Result<()> {
+    match args.command {
+        TextCommand::List { file, format } => {
+            let entries = read_text(&file)?;
+            if format.format != Format::Text {
+                return print_text_report(&file, &entries, format.format);
+            }
+
+            let mut stdout = io::stdout().lock();
+            for (entry, _) in entries {
+                writeln!(stdout, "{}: {}", entry.keyword(), entry.text())?;
+            }
+            Ok(())
+        }
+        TextCommand::Get {
+            file,
+            keyword,
+            format,
+        } => {
+            let entries: Vec<(TextChunk, ChunkInfo)> = read_text(&file)?
+                .into_iter()
+                .filter(|(entry, _)| entry.keyword() == keyword)
+                .collect();
+            if entries.is_empty() {
+                return Err(Error::ChunkNotFound(format!("with keyword '{}'", keyword)));
+            }
+
+            if format.format != Format::Text {
+                return print_text_report(&file, &entries, format.format);
+            }
+
+            let mut stdout = io::stdout().lock();
+            for (entry, _) in entries {
+                writeln!(stdout, "{}", entry.text())?;
+            }
+            Ok(())
+        }
+        TextCommand::Set(args) => set_text(args),
+        TextCommand::Delete { file, keyword } => {
+            let removed = replace_text(&file, &keyword, None)?;
+            if removed == 0 {
+                return Err(Error::ChunkNotFound(format!("with keyword '{}'", keyword)));
+            }
+
+            print_status(
+                &format!("Removed {} text chunk(s) with keyword '{}'", removed, keyword),
+                stdio::is_stdio(&file),
+            )
+        }
+    }
+}
+
+fn set_text(args: TextSetArgs) -> Result<()> {
+    let international = args.international
+        || args.language.is_some()
+        || args.translated_keyword.is_some()
+        || !text::is_latin1(&args.text);
+
+    let entry = if international {
+        TextChunk::International {
+            keyword: args.keyword.clone(),
+            compressed: args.compressed,
+            language_tag: args.language.unwrap_or_default(),
+            translated_keyword: args.translated_keyword.unwrap_or_default(),
+            text: args.text,
+        }
+    } else if args.compressed {
+        TextChunk::Compressed {
+            keyword: args.keyword.clone(),
+            text: args.text,
+        }
+    } else {
+        TextChunk::Text {
+            keyword: args.keyword.clone(),
+            text: args.text,
+        }
+    };
+
+    let chunk = entry.to_chunk()?;
+    replace_text(&args.file, &args.keyword, Some(&chunk))?;
+
+    print_status(
+        &format!("Stored {} chunk with keyword '{}'", chunk.chunk_type(), args.keyword),
+        stdio::is_stdio(&args.file),
+    )
+}
+
+/// Every text entry in the file, with the chunk it is stored in.
+fn read_text(path: &Path) -> Result<Vec<(TextChunk, ChunkInfo)>> {
+    let mut offset = Png::STANDARD_HEADER.len() as u64;
+    let mut entries = Vec::new();
+    for chunk in open_png(path)? {
+        let chunk = chunk?;
+        let info = ChunkInfo::new(&chunk, offset);
+        offset = info.next_offset();
+        if TextChunk::is_text_chunk(chunk.chunk_type()) {
+            match TextChunk::try_from(&chunk) {
+                Ok(entry) => entries.push((entry, info)),
+                Err(err) => warn_malformed_text(&chunk, info.offset, &err),
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Reports a text chunk that cannot be parsed. Reading and editing text
+/// both skip such chunks, leaving them in the file, rather than failing
+/// for the whole file.
+fn warn_malformed_text(chunk: &Chunk, offset: u64, err: &Error) {
+    eprintln!(
+        "Warning: skipping malformed {} chunk at offset {}: {}",
+        chunk.chunk_type(),
+        offset,
+        err
+    );
+}
+
+/// A text entry, for `text list` and `text get` with `--format json` or
+/// `yaml`. Latin-1 entries have an empty language tag and translation.
+#[derive(Serialize)]
+struct TextEntry<'a> {
+    keyword: &'a str,
+    text: &'a str,
+    compressed: bool,
+    language_tag: &'a str,
+    translated_keyword: &'a str,
+    chunk: &'a ChunkInfo,
+}
+
+#[derive(Serialize)]
+struct TextReport<'a> {
+    file: &'a Path,
+    entries: Vec<TextEntry<'a>>,
+}
+
+fn print_text_report(
+    file: &Path,
+    entries: &[(TextChunk, ChunkInfo)],
+    format: Format,
+) -> Result<()> {
+    let entries = entries
+        .iter()
+        .map(|(entry, chunk)| {
+            let (compressed, language_tag, translated_keyword) = match entry {
+                TextChunk::Text { .. } => (false, "", ""),
+                TextChunk::Compressed { .. } => (true, "", ""),
+                TextChunk::International {
+                    compressed,
+                    language_tag,
+                    translated_keyword,
+                    ..
+                } => (*compressed, language_tag.as_str(), translated_keyword.as_str()),
+            };
+
+            TextEntry {
+                keyword: entry.keyword(),
+                text: entry.text(),
+                compressed,
+                language_tag,
+                translated_keyword,
+                chunk,
+            }
+        })
+        .collect();
+
+    print_document(format, &TextReport { file, entries })
+}
+
+/// Drops every text chunk with `keyword` and inserts `replacement`, if any,
+/// before IEND. Returns how many chunks were dropped.
+fn replace_text(path: &Path, keyword: &str, replacement: Option<&Chunk>) -> Result<usize> {
+    let mut removed = 0;
+
+    rewrite(path, None, None, |reader, writer| {
+        let mut inserted = false;
+        let mut offset = Png::STANDARD_HEADER.len() as u64;
+        while let Some(header) = reader.next_header()? {
+            let chunk_offset = offset;
+            offset += (Chunk::METADATA_LENGTH + header.length as usize) as u64;
+            if !inserted && header.chunk_type.to_string() == "IEND" {
+                replacement.into_iter().try_for_each(|chunk| writer.write_chunk(chunk))?;
+                inserted = true;
+            }
+
+            if !TextChunk::is_text_chunk(&header.chunk_type) {
+                writer.copy_chunk(&header, reader)?;
+                continue;
+            }
+
+            let chunk = Chunk::new(header.chunk_type, reader.read_data()?);
+            // Leave entries we cannot parse alone rather than refusing to edit the file
+            match TextChunk::try_from(&chunk) {
+                Ok(entry) if entry.keyword() == keyword => removed += 1,
+                Ok(_) => writer.write_chunk(&chunk)?,
+                Err(err) => {
+                    warn_malformed_text(&chunk, chunk_offset, &err);
+                    writer.write_chunk(&chunk)?;
+                }
+            }
+        }
+
+        if !inserted {
+            replacement.into_iter().try_for_each(|chunk| writer.write_chunk(chunk))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(removed)
+}
+
+/// The data after `IEND`, for `trailer show --format json` or `yaml`.
+#[derive(Serialize)]
+struct TrailerReport<'a> {
+    file: &'a Path,
+    #[serde(flatten)]
+    trailer: TrailerInfo,
+}
+
+pub fn trailer(args: TrailerArgs) -> Result<()> {
+    match args.command {
+        TrailerCommand::Show { file, format } => {
+            let png = read_png(&file)?;
+            let trailer = png.trailer();
+            if format.format != Format::Text {
+                let report = TrailerReport {
+                    file: &file,
+                    trailer: TrailerInfo::new(trailer, png.trailer_offset() as u64),
+                };
+                return print_document(format.format, &report);
+            }
+
+            let mut stdout = io::stdout().lock();
+            if trailer.is_empty() {
+                writeln!(stdout, "No data after IEND")?;
+                return Ok(());
+            }
+
+            writeln!(
+                stdout,
+                "{} bytes of data after IEND, at offset {}",
+                trailer.len(),
+                png.trailer_offset()
+            )?;
+            print_hex_preview(&mut stdout, trailer)
+        }
+        TrailerCommand::Extract { file, output } => {
+            let trailer = read_png(&file)?.take_trailer();
+            match output {
+                Some(output) if !stdio::is_stdio(&output) => {
+                    fs::write(&output, &trailer)?;
+                    print_status(
+                        &format!("Wrote {} bytes to {}", trailer.len(), output.display()),
+                        false,
+                    )
+                }
+                _ => Ok(io::stdout().write_all(&trailer)?),
+            }
+        }
+        TrailerCommand::Strip { file, output } => {
+            let mut removed = 0;
+            rewrite(&file, output.as_deref(), None, |reader, writer| {
+                while let Some(header) = reader.next_header()? {
+                    writer.copy_chunk(&header, reader)?;
+                }
+
+                removed = reader.copy_trailer(&mut io::sink())?;
+                Ok(())
+            })?;
+
+            print_status(
+                &format!("Removed {} bytes of data after IEND", removed),
+                writes_to_stdout(&file, output.as_deref()),
+            )
+        }
+        TrailerCommand::Write(args) => write_trailer(args),
+    }
+}
+
+fn write_trailer(args: TrailerWriteArgs) -> Result<()> {
+    check_stdin(&[
+        stdio::is_stdio(&args.file),
+        args.input.as_deref().is_some_and(stdio::is_stdio),
+    ])?;
+
+    // Clap requires one of the two
+    let data = match &args.input {
+        Some(input) => stdio::read(input)?,
+        None => args.message.clone().unwrap_or_default().into_bytes(),
+    };
+
+    rewrite(&args.file, args.output.as_deref(), None, |reader, writer| {
+        while let Some(header) = reader.next_header()? {
+            writer.copy_chunk(&header, reader)?;
+        }
+
+        if !reader.is_ended() {
+            return Err(Error::InvalidImage(
+                "data can only be appended after an IEND chunk".to_string(),
+            ));
+        }
+
+        reader.copy_trailer(&mut io::sink())?;
+        writer.write_trailer(&data)
+    })?;
+
+    print_status(
+        &format!("Wrote {} bytes after IEND", data.len()),
+        writes_to_stdout(&args.file, args.output.as_deref()),
+    )
+}
+
+/// Prints the first few rows of `data` as hex and printable ASCII.
+fn print_hex_preview(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    const ROW: usize = 16;
+    const ROWS: usize = 4;
+
+    for (index, row) in data.chunks(ROW).take(ROWS).enumerate() {
+        let hex: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = row
+            .iter()
+            .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+            .collect();
+        writeln!(out, "  {:08x}  {:<47}  {}", index * ROW, hex.join(" "), text)?;
+    }
+
+    if data.len() > ROW * ROWS {
+        writeln!(out, "  ...")?;
+    }
+
+    Ok(())
+}
+
+/// A scan result, for `scan --format json` or `yaml`.
+#[derive(Serialize)]
+struct ScanReport<'a> {
+    file: &'a Path,
+    #[serde(flatten)]
+    report: &'a Report,
+}
+
+pub fn scan(args: ScanArgs) -> Result<()> {
+    let format = args.format.format;
+    let mut batch = Batch::expand(&args.files, args.batch.recursive)?;
+    // Reports start with the file name
+    batch.headers = false;
+
+    batch.run(args.batch.jobs, |target, out| {
+        let report = scan::scan(&read_png(&target.path)?);
+        match format {
+            Format::Text => print_scan_report(&target.path, &report, out),
+            format => {
+                let report = ScanReport {
+                    file: &target.path,
+                    report: &report,
+                };
+                write_document(out, format, &report, batch.is_batch)
+            }
+        }
+    })
+}
+
+fn print_scan_report(file: &Path, report: &Report, out: &mut Vec<u8>) -> Result<()> {
+    if report.reasons.is_empty() {
+        writeln!(out, "{}: score 0/100, nothing suspicious", file.display())?;
+        return Ok(());
+    }
+
+    writeln!(out, "{}: score {}/100", file.display(), report.score)?;
+    for reason in &report.reasons {
+        writeln!(out, "  +{:<3} {}", reason.score, reason.message)?;
+    }
+
+    Ok(())
+}
+
+pub fn sign(args: SignArgs) -> Result<()> {
+    check_stdin(&[stdio::is_stdio(&args.file), stdio::is_stdio(&args.key)])?;
+    let key = signature::parse_signing_key(&stdio::read(&args.key)?)?;
+    let scope = match &args.chunk_type {
+        Some(chunk_type) => Scope::Payload(ChunkType::from_str(chunk_type)?),
+        None => Scope::Critical,
+    };
+
+    let mut batch = Batch::expand(std::slice::from_ref(&args.file), args.batch.recursive)?;
+    batch.check_output(args.output.as_deref())?;
+    batch.output_to_stderr = writes_to_stdout(&args.file, args.output.as_deref());
+
+    batch.run(args.batch.jobs, |target, out| {
+        let mut png = read_png(&target.path)?;
+        let signature = Signature::sign(&png, &key, scope.clone())?;
+
+        // Signing again with the same key and scope replaces the signature
+        png.remove_chunks(|chunk| {
+            Signature::try_from(chunk)
+                .is_ok_and(|old| old.key_id == signature.key_id && old.scope == signature.scope)
+        });
+        png.append_chunk(signature.to_chunk());
+
+        let output = batch.output_path(target, args.output.as_deref())?;
+        write_png(&target.path, output.as_deref(), args.backup.as_deref(), &png.as_bytes())?;
+        writeln!(out, "Signed {} with key {}", signature.scope, signature.key_id)?;
+        Ok(())
+    })
+}
+
+/// The signatures in a file, for `verify --format json` or `yaml`.
+#[derive(Serialize)]
+struct VerifyReport<'a> {
+    file: &'a Path,
+    /// The ID of the public key checked against.
+    key_id: String,
+    signatures: Vec<SignatureReport>,
+}
+
+#[derive(Serialize)]
+struct SignatureReport {
+    /// The signer's key ID, or null if the chunk could not be read.
+    key_id: Option<String>,
+    /// `payload` or `critical`, or null if the chunk could not be read.
+    scope: Option<&'static str>,
+    /// The payload's chunk type, or null for the critical chunks.
+    chunk_type: Option<String>,
+    /// Whether the signature matches, or null if it was made by another key
+    /// and so could not be checked. Chunks that could not be read are
+    /// invalid.
+    valid: Option<bool>,
+    /// Why the chunk could not be read, or null.
+    error: Option<String>,
+}
+
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let reads_stdin = args.files.iter().any(|file| stdio::is_stdio(file));
+    check_stdin(&[reads_stdin, stdio::is_stdio(&args.public_key)])?;
+    let key = signature::parse_verifying_key(&stdio::read(&args.public_key)?)?;
+    let key_id = KeyId::of(&key);
+    let format = args.format.format;
+
+    let mut batch = Batch::expand(&args.files, args.batch.recursive)?;
+    // Reports name their file
+    batch.headers = format == Format::Text;
+
+    batch.run(args.batch.jobs, |target, out| {
+        let png = read_png(&target.path)?;
+        let mut reports = Vec::new();
+        for signature in signature::signatures(&png) {
+            let signature = match signature {
+                Ok(signature) => signature,
+                Err(err) => {
+                    if format == Format::Text {
+                        writeln!(out, "INVALID {} chunk: {}", signature::CHUNK_TYPE, err)?;
+                    }
+                    reports.push(SignatureReport {
+                        key_id: None,
+                        scope: None,
+                        chunk_type: None,
+                        valid: Some(false),
+                        error: Some(err.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            let valid = if signature.key_id == key_id {
+                match signature.verify(&png, &key) {
+                    Ok(()) => Some(true),
+                    Err(Error::SignatureMismatch { .. }) => Some(false),
+                    Err(err) => return Err(err),
+                }
+            } else {
+                None
+            };
+
+            if format == Format::Text {
+                let (status, note) = match valid {
+                    Some(true) => ("Valid", ""),
+                    Some(false) => ("INVALID", ": the image was altered after signing"),
+                    None => ("Skipped", ", made with a different key"),
+                };
+                writeln!(
+                    out,
+                    "{} signature by key {} over {}{}",
+                    status, signature.key_id, signature.scope, note
+                )?;
+            }
+
+            let (scope, chunk_type) = match &signature.scope {
+                Scope::Payload(chunk_type) => ("payload", Some(chunk_type.to_string())),
+                Scope::Critical => ("critical", None),
+            };
+            reports.push(SignatureReport {
+                key_id: Some(signature.key_id.to_string()),
+                scope: Some(scope),
+                chunk_type,
+                valid,
+                error: None,
+            });
+        }
+
+        if format != Format::Text {
+            let report = VerifyReport {
+                file: &target.path,
+                key_id: key_id.to_string(),
+                signatures: reports,
+            };
+            write_document(out, format, &report, batch.is_batch)?;
+            return check_signatures(&report.signatures, key_id);
+        }
+
+        check_signatures(&reports, key_id)
+    })
+}
+
+/// Fails unless some signature by the key was checked, all of them matched
+/// and every signature chunk could be read.
+fn check_signatures(reports: &[SignatureReport], key_id: KeyId) -> Result<()> {
+    if reports.iter().any(|report| report.error.is_none() && report.valid == Some(false)) {
+        return Err(Error::SignatureMismatch {
+            key_id: key_id.to_string(),
+        });
+    }
+
+    let unreadable = reports.iter().filter(|report| report.error.is_some()).count();
+    if unreadable > 0 {
+        return Err(Error::InvalidPayload(format!(
+            "{} {} chunk(s) could not be read",
+            unreadable,
+            signature::CHUNK_TYPE
+        )));
+    }
+
+    if reports.iter().all(|report| report.valid.is_none()) {
+        return Err(Error::ChunkNotFound(format!(
+            "{} signed by key {}",
+            signature::CHUNK_TYPE,
+            key_id
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let key = signature::generate_key();
+    let mut public_path = args.path.clone().into_os_string();
+    public_path.push(".pub");
+    let public_path = PathBuf::from(public_path);
+
+    // Never overwrite a key, and keep the secret one private
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut secret = options.open(&args.path)?;
+    writeln!(secret, "{}", signature::encode_key(&key.to_bytes()))?;
+    let mut public = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&public_path)?;
+    writeln!(public, "{}", signature::encode_key(key.verifying_key().as_bytes()))?;
+
+    print_status(
+        &format!(
+            "Wrote secret key {} and public key {} (key ID {})",
+            args.path.display(),
+            public_path.display(),
+            KeyId::of(&key.verifying_key())
+        ),
+        false,
+    )
+}
+
+pub fn add_integrity(args: AddIntegrityArgs) -> Result<()> {
+    let mut batch = Batch::expand(std::slice::from_ref(&args.file), args.batch.recursive)?;
+    batch.check_output(args.output.as_deref())?;
+    batch.output_to_stderr = writes_to_stdout(&args.file, args.output.as_deref());
+
+    batch.run(args.batch.jobs, |target, out| {
+        let mut png = read_png(&target.path)?;
+        let manifest = Manifest::new(&png)?;
+
+        // A file has one manifest, so adding it again refreshes it
+        png.remove_chunks(|chunk| chunk.chunk_type().to_string() == integrity::CHUNK_TYPE);
+        png.append_chunk(manifest.to_chunk());
+
+        let output = batch.output_path(target, args.output.as_deref())?;
+        write_png(&target.path, output.as_deref(), args.backup.as_deref(), &png.as_bytes())?;
+        writeln!(out, "Stored image data SHA-256 {}", manifest.digest)?;
+        Ok(())
+    })
+}
+
+/// The result of checking a file, for `check-integrity --format json` or
+/// `yaml`.
+#[derive(Serialize)]
+struct IntegrityReport<'a> {
+    file: &'a Path,
+    /// The digest stored in the manifest.
+    expected: String,
+    /// The digest of the image data as it is now.
+    found: String,
+    valid: bool,
+}
+
+pub fn check_integrity(args: CheckIntegrityArgs) -> Result<()> {
+    let format = args.format.format;
+    let mut batch = Batch::expand(&args.files, args.batch.recursive)?;
+    // Reports name their file
+    batch.headers = format == Format::Text;
+
+    batch.run(args.batch.jobs, |target, out| {
+        let png = read_png(&target.path)?;
+        let manifest = Manifest::find(&png)?;
+        let found = Digest::of(&png)?;
+
+        if format == Format::Text {
+            if found == manifest.digest {
+                writeln!(out, "Image data matches SHA-256 {}", found)?;
+            } else {
+                writeln!(out, "Image data MODIFIED since SHA-256 {} was stored", manifest.digest)?;
+            }
+        } else {
+            let report = IntegrityReport {
+                file: &target.path,
+                expected: manifest.digest.to_string(),
+                found: found.to_string(),
+                valid: found == manifest.digest,
+            };
+            write_document(out, format, &report, batch.is_batch)?;
+        }
+
+        manifest.check(&png)
+    })
+}
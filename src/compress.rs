@@ -0,0 +1,238 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::{Error, Result};
+
+/// Marks chunk data produced by [`compress`].
+const MAGIC: [u8; 4] = *b"PSCZ";
+
+/// Magic, codec and uncompressed length.
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 8;
+
+/// Default limit on how large [`decompress`] lets a payload grow.
+pub const DEFAULT_MAX_SIZE: usize = 256 * 1024 * 1024;
+
+/// How a payload is compressed before it is hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    /// Stored as is, behind the same header as the other codecs.
+    #[default]
+    None,
+    Deflate,
+    Zstd,
+    Brotli,
+}
+
+impl Codec {
+    fn id(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Deflate => 1,
+            Codec::Zstd => 2,
+            Codec::Brotli => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Codec> {
+        match id {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Deflate),
+            2 => Ok(Codec::Zstd),
+            3 => Ok(Codec::Brotli),
+            _ => Err(Error::InvalidPayload(format!("unknown compression codec {}", id))),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        match string {
+            "none" => Ok(Codec::None),
+            "deflate" => Ok(Codec::Deflate),
+            "zstd" => Ok(Codec::Zstd),
+            "brotli" => Ok(Codec::Brotli),
+            _ => Err(Error::InvalidArgument(format!(
+                "unknown codec '{}', expected none, deflate, zstd or brotli",
+                string
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Codec::None => "none",
+            Codec::Deflate => "deflate",
+            Codec::Zstd => "zstd",
+            Codec::Brotli => "brotli",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Whether `data` starts with the header written by [`compress`].
+fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Compresses `data` behind a header recording the codec and original
+/// length. The header is written for every codec, [`Codec::None`]
+/// included, so [`decompress`] never has to guess whether there is one.
+pub fn compress(data: &[u8], codec: Codec) -> Result<Vec<u8>> {
+    let capacity = match codec {
+        Codec::None => HEADER_LENGTH + data.len(),
+        _ => HEADER_LENGTH + data.len() / 2,
+    };
+    let mut header = Vec::with_capacity(capacity);
+    header.extend_from_slice(&MAGIC);
+    header.push(codec.id());
+    header.extend_from_slice(&(data.len() as u64).to_be_bytes());
+
+    match codec {
+        Codec::None => {
+            header.extend_from_slice(data);
+            Ok(header)
+        }
+        Codec::Deflate => {
+            let mut encoder = DeflateEncoder::new(header, Compression::best());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        Codec::Zstd => {
+            let mut encoder = zstd::Encoder::new(header, 19)?;
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        Codec::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(header, 4096, 11, 22);
+            encoder.write_all(data)?;
+            encoder.flush()?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// Reverses [`compress`], refusing to produce more than `max_size` bytes
+/// so a small crafted payload cannot exhaust memory.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    if !is_compressed(data) {
+        return Err(Error::InvalidPayload("data is not compressed".to_string()));
+    }
+
+    if data.len() < HEADER_LENGTH {
+        return Err(Error::InvalidPayload(
+            "compression header is truncated".to_string(),
+        ));
+    }
+
+    let codec = Codec::from_id(data[MAGIC.len()])?;
+    let mut length = [0; 8];
+    length.copy_from_slice(&data[MAGIC.len() + 1..HEADER_LENGTH]);
+    let length = u64::from_be_bytes(length);
+    if length > max_size as u64 {
+        return Err(Error::PayloadTooLarge { limit: max_size });
+    }
+
+    let compressed = &data[HEADER_LENGTH..];
+    let reader: Box<dyn Read> = match codec {
+        Codec::None => Box::new(compressed),
+        Codec::Deflate => Box::new(DeflateDecoder::new(compressed)),
+        Codec::Zstd => Box::new(zstd::Decoder::with_buffer(compressed)?),
+        Codec::Brotli => Box::new(brotli::Decompressor::new(compressed, 4096)),
+    };
+
+    // Read one byte past the claimed length to catch payloads that lie
+    let mut output = Vec::new();
+    reader
+        .take(length + 1)
+        .read_to_end(&mut output)
+        .map_err(|err| Error::InvalidPayload(format!("{} data is corrupt: {}", codec, err)))?;
+
+    if output.len() as u64 != length {
+        return Err(Error::InvalidPayload(format!(
+            "{} data decompresses to {} bytes, header says {}",
+            codec,
+            output.len(),
+            length
+        )));
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        br#"{"level":"info","message":"request handled","status":200}"#
+            .repeat(50)
+    }
+
+    #[test]
+    fn test_round_trip_all_codecs() {
+        let payload = testing_payload();
+
+        for codec in [Codec::Deflate, Codec::Zstd, Codec::Brotli] {
+            let compressed = compress(&payload, codec).unwrap();
+            assert!(is_compressed(&compressed));
+            assert!(compressed.len() < payload.len() / 4, "{} did not compress", codec);
+            assert_eq!(decompress(&compressed, DEFAULT_MAX_SIZE).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_none_keeps_header() {
+        let payload = testing_payload();
+        let compressed = compress(&payload, Codec::None).unwrap();
+
+        assert!(is_compressed(&compressed));
+        assert_eq!(&compressed[HEADER_LENGTH..], payload);
+        assert_eq!(decompress(&compressed, DEFAULT_MAX_SIZE).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_data_that_looks_compressed() {
+        let payload = b"PSCZ is how this message starts".to_vec();
+        let compressed = compress(&payload, Codec::None).unwrap();
+
+        assert_eq!(decompress(&compressed, DEFAULT_MAX_SIZE).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let compressed = compress(&testing_payload(), Codec::Zstd).unwrap();
+
+        assert!(matches!(
+            decompress(&compressed, 100),
+            Err(Error::PayloadTooLarge { limit: 100 })
+        ));
+    }
+
+    #[test]
+    fn test_understated_length() {
+        let mut compressed = compress(&testing_payload(), Codec::Deflate).unwrap();
+        compressed[12] = 10;
+
+        assert!(matches!(
+            decompress(&compressed, DEFAULT_MAX_SIZE),
+            Err(Error::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_codec() {
+        assert_eq!(Codec::from_str("brotli").unwrap(), Codec::Brotli);
+        assert_eq!(Codec::Zstd.to_string(), "zstd");
+        assert!(Codec::from_str("lzma").is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand_core::{OsRng, RngCore};
+
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+
+/// Marks chunk data produced by [`encrypt`].
+const MAGIC: [u8; 4] = *b"PSEC";
+const VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+const CHECK_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 12 + SALT_LENGTH + CHECK_LENGTH + NONCE_LENGTH;
+
+/// Upper bounds on the Argon2 cost read back from a payload, so a crafted
+/// file cannot make `decrypt` allocate gigabytes or spin for minutes.
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+const MAX_PARALLELISM: u32 = 16;
+
+/// Argon2id cost parameters. They are stored next to the salt so payloads
+/// written with different settings can still be decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Whether `data` starts with the header written by [`encrypt`].
+fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `plaintext` with XChaCha20-Poly1305 under a key derived from
+/// `secret` with Argon2id, using the default cost parameters.
+pub fn encrypt(plaintext: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    encrypt_with_params(plaintext, secret, KdfParams::default())
+}
+
+/// Like [`encrypt`], with explicit Argon2id cost parameters.
+///
+/// The output is a versioned header (magic, version, KDF parameters, salt,
+/// key check value and nonce) followed by the ciphertext. The whole header
+/// is authenticated along with the ciphertext.
+pub fn encrypt_with_params(plaintext: &[u8], secret: &[u8], params: KdfParams) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let (key, check) = derive_keys(secret, &salt, params)?;
+
+    let mut payload = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + 16);
+    payload.extend_from_slice(&MAGIC);
+    payload.push(VERSION);
+    payload.extend_from_slice(&params.memory_kib.to_be_bytes());
+    payload.extend_from_slice(&params.iterations.to_be_bytes());
+    payload.extend_from_slice(&params.parallelism.to_be_bytes());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&check);
+    payload.extend_from_slice(&nonce);
+
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &payload,
+            },
+        )
+        .map_err(|_| Error::InvalidPayload("encryption failed".to_string()))?;
+
+    payload.extend_from_slice(&ciphertext);
+    Ok(payload)
+}
+
+/// Reverses [`encrypt`]. A key check value stored in the header tells a
+/// wrong secret ([`Error::WrongPassphrase`]) apart from data modified after
+/// encryption ([`Error::PayloadTampered`]).
+pub fn decrypt(payload: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    if !is_encrypted(payload) {
+        return Err(Error::InvalidPayload("data is not encrypted".to_string()));
+    }
+
+    if payload.len() < HEADER_LENGTH {
+        return Err(Error::InvalidPayload(
+            "encryption header is truncated".to_string(),
+        ));
+    }
+
+    let version = payload[MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::InvalidPayload(format!(
+            "unsupported encryption version {}",
+            version
+        )));
+    }
+
+    let (header, ciphertext) = payload.split_at(HEADER_LENGTH);
+    let field = |offset: usize| {
+        let start = MAGIC.len() + 1 + offset;
+        u32::from_be_bytes([
+            header[start],
+            header[start + 1],
+            header[start + 2],
+            header[start + 3],
+        ])
+    };
+    let params = KdfParams {
+        memory_kib: field(0),
+        iterations: field(4),
+        parallelism: field(8),
+    };
+
+    let salt_start = MAGIC.len() + 1 + 12;
+    let check_start = salt_start + SALT_LENGTH;
+    let nonce_start = check_start + CHECK_LENGTH;
+    let salt = &header[salt_start..check_start];
+    let stored_check = &header[check_start..nonce_start];
+    let nonce = XNonce::from_slice(&header[nonce_start..]);
+
+    let (key, check) = derive_keys(secret, salt, params)?;
+    if check[..] != stored_check[..] {
+        return Err(Error::WrongPassphrase);
+    }
+
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::PayloadTampered)
+}
+
+/// Salt for [`derive_chunk_type`]. It has to be fixed, since decode needs
+/// the type before it has read anything from the file; it only keeps this
+/// derivation apart from others made from the same secret.
+const CHUNK_TYPE_SALT: &[u8] = b"png-secret chunk type v1";
+
+/// The chunk type `auto` stands for when there is a secret: one of the
+/// types [`ChunkType::random`] produces, derived with Argon2id at the
+/// default cost so a payload can be found again from the secret alone.
+///
+/// The type is stored in clear, so it is a check on the secret that needs
+/// no payload: about one wrong guess in 456,976 (26^4) matches it. As the
+/// salt is the same for every image, one Argon2id pass over a dictionary
+/// can be used against all of them, leaving only a few guesses to try on
+/// each payload. It also gives every image hidden under the same secret
+/// the same type, so they can be linked without knowing the secret. Use an
+/// explicit or random type where either matters.
+pub fn derive_chunk_type(secret: &[u8]) -> Result<ChunkType> {
+    let (key, _) = derive_keys(secret, CHUNK_TYPE_SALT, KdfParams::default())?;
+    Ok(ChunkType::inconspicuous([key[0], key[1], key[2], key[3]]))
+}
+
+/// Derives the encryption key and an independent key check value.
+fn derive_keys(
+    secret: &[u8],
+    salt: &[u8],
+    params: KdfParams,
+) -> Result<([u8; 32], [u8; CHECK_LENGTH])> {
+    if params.memory_kib > MAX_MEMORY_KIB
+        || params.iterations > MAX_ITERATIONS
+        || params.parallelism > MAX_PARALLELISM
+    {
+        return Err(Error::InvalidPayload(
+            "key derivation parameters are out of range".to_string(),
+        ));
+    }
+
+    let argon2_params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(32 + CHECK_LENGTH),
+    )
+    .map_err(|err| Error::InvalidPayload(format!("key derivation failed: {}", err)))?;
+
+    let mut output = [0; 32 + CHECK_LENGTH];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+        .hash_password_into(secret, salt, &mut output)
+        .map_err(|err| Error::InvalidPayload(format!("key derivation failed: {}", err)))?;
+
+    let mut key = [0; 32];
+    let mut check = [0; CHECK_LENGTH];
+    key.copy_from_slice(&output[..32]);
+    check.copy_from_slice(&output[32..]);
+
+    Ok((key, check))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap parameters so the tests stay fast
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let payload = encrypt_with_params(b"secret message", b"hunter2", TEST_PARAMS).unwrap();
+
+        assert!(is_encrypted(&payload));
+        assert_eq!(decrypt(&payload, b"hunter2").unwrap(), b"secret message");
+    }
+
+    #[test]
+    fn test_derive_chunk_type() {
+        let chunk_type = derive_chunk_type(b"correct horse").unwrap();
+
+        assert!(!chunk_type.is_critical() && !chunk_type.is_public());
+        assert!(chunk_type.is_valid() && chunk_type.is_safe_to_copy());
+        assert_eq!(derive_chunk_type(b"correct horse").unwrap(), chunk_type);
+        assert_ne!(derive_chunk_type(b"battery staple").unwrap(), chunk_type);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let payload = encrypt_with_params(b"secret message", b"hunter2", TEST_PARAMS).unwrap();
+
+        assert!(matches!(
+            decrypt(&payload, b"hunter3"),
+            Err(Error::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_ciphertext() {
+        let mut payload = encrypt_with_params(b"secret message", b"hunter2", TEST_PARAMS).unwrap();
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+
+        assert!(matches!(
+            decrypt(&payload, b"hunter2"),
+            Err(Error::PayloadTampered)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut payload = encrypt_with_params(b"secret message", b"hunter2", TEST_PARAMS).unwrap();
+        payload[4] = 2;
+
+        assert!(matches!(
+            decrypt(&payload, b"hunter2"),
+            Err(Error::InvalidPayload(_))
+        ));
+    }
+}
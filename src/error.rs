@@ -0,0 +1,209 @@
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+use crate::chunk_type::ChunkTypeError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while reading, editing or writing a PNG.
+#[derive(Debug)]
+pub enum Error {
+    /// The file does not start with the 8-byte PNG signature.
+    InvalidSignature,
+    /// A chunk needs more bytes than are left in the input.
+    TruncatedChunk { needed: usize, available: usize },
+    /// A chunk was given more bytes than its length field declares.
+    LengthMismatch { declared: u32, actual: usize },
+    /// A chunk declares more than 2^31 - 1 bytes of data.
+    ChunkTooLong(u32),
+    /// The CRC stored in a chunk does not match its type and data.
+    CrcMismatch { expected: u32, found: u32 },
+    /// A chunk type is not four ASCII letters, or cannot be used here.
+    InvalidChunkType(ChunkTypeError),
+    /// No chunk of the requested type exists in the file.
+    ChunkNotFound(String),
+    /// Chunk data was expected to be UTF-8 text but is not.
+    InvalidUtf8(FromUtf8Error),
+    /// Chunk data does not have the layout its header promises.
+    InvalidPayload(String),
+    /// The payload is encrypted but no passphrase or key file was given.
+    PassphraseRequired,
+    /// The passphrase or key file does not match the one used to encrypt.
+    WrongPassphrase,
+    /// The key is right but the encrypted payload was modified.
+    PayloadTampered,
+    /// Image data is missing or corrupt.
+    InvalidImage(String),
+    /// The image is valid but uses a format this operation cannot handle.
+    UnsupportedImage(String),
+    /// The payload does not fit in the space the image offers.
+    InsufficientCapacity { needed: usize, available: usize },
+    /// A tEXt, zTXt or iTXt chunk is malformed or cannot be written.
+    InvalidTextChunk(String),
+    /// `validate` found errors in the file.
+    ValidationFailed { errors: usize },
+    /// Some of the files a command was given could not be processed.
+    FilesFailed { failed: usize, total: usize },
+    /// An Ed25519 signature embedded by `sign` does not match what it
+    /// covers. Not to be confused with [`Error::InvalidSignature`], the PNG
+    /// file signature.
+    SignatureMismatch { key_id: String },
+    /// The image data no longer hashes to the digest in its integrity
+    /// manifest.
+    IntegrityMismatch { expected: String, found: String },
+    /// A compressed payload would grow past the allowed size.
+    PayloadTooLarge { limit: usize },
+    /// Some pieces of a payload split over several chunks are missing:
+    /// `missing_count` of them, the first few of which are listed.
+    IncompletePayload {
+        payload_id: u64,
+        missing_count: u32,
+        missing: Vec<u32>,
+    },
+    /// An option given by the caller makes no sense.
+    InvalidArgument(String),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+}
+
+impl Error {
+    /// The process exit code the CLI reports for this error. Codes 1 and 2
+    /// are left to the runtime and to argument parsing.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(_) => 3,
+            Error::InvalidSignature => 4,
+            Error::TruncatedChunk { .. } => 5,
+            Error::LengthMismatch { .. } => 6,
+            Error::ChunkTooLong(_) => 7,
+            Error::CrcMismatch { .. } => 8,
+            Error::InvalidChunkType(_) => 9,
+            Error::ChunkNotFound(_) => 10,
+            Error::InvalidUtf8(_) => 11,
+            Error::InvalidPayload(_) => 12,
+            Error::PassphraseRequired => 13,
+            Error::WrongPassphrase => 14,
+            Error::PayloadTampered => 15,
+            Error::InvalidImage(_) => 16,
+            Error::UnsupportedImage(_) => 17,
+            Error::InsufficientCapacity { .. } => 18,
+            Error::InvalidArgument(_) => 19,
+            Error::IncompletePayload { .. } => 20,
+            Error::PayloadTooLarge { .. } => 21,
+            Error::InvalidTextChunk(_) => 22,
+            Error::ValidationFailed { .. } => 23,
+            Error::FilesFailed { .. } => 24,
+            Error::SignatureMismatch { .. } => 25,
+            Error::IntegrityMismatch { .. } => 26,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidSignature => write!(f, "File does not start with the PNG signature"),
+            Error::TruncatedChunk { needed, available } => write!(
+                f,
+                "Chunk is truncated: needed {} bytes, only {} available",
+                needed, available
+            ),
+            Error::LengthMismatch { declared, actual } => write!(
+                f,
+                "Chunk declares {} data bytes but was given {} bytes",
+                declared, actual
+            ),
+            Error::ChunkTooLong(length) => {
+                write!(f, "Chunk length {} exceeds 2^31 - 1 bytes", length)
+            }
+            Error::CrcMismatch { expected, found } => write!(
+                f,
+                "Chunk CRC mismatch: expected {}, found {}",
+                expected, found
+            ),
+            Error::InvalidChunkType(_) => write!(f, "Invalid chunk type"),
+            Error::ChunkNotFound(chunk_type) => write!(f, "Chunk {} not found", chunk_type),
+            Error::InvalidUtf8(_) => write!(f, "Chunk data is not valid UTF-8"),
+            Error::InvalidPayload(reason) => write!(f, "Invalid payload: {}", reason),
+            Error::PassphraseRequired => {
+                write!(f, "Payload is encrypted; a passphrase or key file is required")
+            }
+            Error::WrongPassphrase => write!(f, "Wrong passphrase or key file"),
+            Error::PayloadTampered => {
+                write!(f, "Encrypted payload was modified after it was written")
+            }
+            Error::InvalidImage(reason) => write!(f, "Invalid image: {}", reason),
+            Error::UnsupportedImage(reason) => write!(f, "Unsupported image: {}", reason),
+            Error::InsufficientCapacity { needed, available } => write!(
+                f,
+                "Payload needs {} bytes but the image only has room for {}",
+                needed, available
+            ),
+            Error::InvalidTextChunk(reason) => write!(f, "Invalid text chunk: {}", reason),
+            Error::ValidationFailed { errors } => {
+                write!(f, "File failed validation with {} error(s)", errors)
+            }
+            Error::FilesFailed { failed, total } => {
+                write!(f, "{} of {} files could not be processed", failed, total)
+            }
+            Error::SignatureMismatch { key_id } => write!(
+                f,
+                "Signature by key {} does not match; the image was altered after signing",
+                key_id
+            ),
+            Error::IntegrityMismatch { expected, found } => write!(
+                f,
+                "Image data was modified: manifest has SHA-256 {}, image hashes to {}",
+                expected, found
+            ),
+            Error::PayloadTooLarge { limit } => {
+                write!(f, "Payload decompresses to more than {} bytes", limit)
+            }
+            Error::IncompletePayload {
+                payload_id,
+                missing_count,
+                missing,
+            } => {
+                write!(f, "Payload {:016x} is missing {} piece(s): ", payload_id, missing_count)?;
+                let listed: Vec<String> = missing.iter().map(u32::to_string).collect();
+                write!(f, "{}", listed.join(", "))?;
+                if *missing_count as usize > missing.len() {
+                    write!(f, ", ...")?;
+                }
+                Ok(())
+            }
+            Error::InvalidArgument(reason) => write!(f, "Invalid argument: {}", reason),
+            Error::Io(_) => write!(f, "I/O error"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidChunkType(err) => Some(err),
+            Error::InvalidUtf8(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ChunkTypeError> for Error {
+    fn from(err: ChunkTypeError) -> Self {
+        Error::InvalidChunkType(err)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(err: FromUtf8Error) -> Self {
+        Error::InvalidUtf8(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
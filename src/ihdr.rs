@@ -0,0 +1,320 @@
+use std::fmt;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+
+/// How each pixel's samples are laid out, from the `IHDR` color type byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorType {
+    /// A single gray sample (0).
+    Grayscale,
+    /// Red, green and blue samples (2).
+    Truecolor,
+    /// An index into the `PLTE` palette (3).
+    Indexed,
+    /// Gray and alpha samples (4).
+    GrayscaleAlpha,
+    /// Red, green, blue and alpha samples (6).
+    TruecolorAlpha,
+}
+
+impl ColorType {
+    /// The byte stored in `IHDR`.
+    pub fn id(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Truecolor => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::TruecolorAlpha => 6,
+        }
+    }
+
+    /// Parses an `IHDR` color type byte.
+    pub fn from_id(id: u8) -> Result<ColorType> {
+        match id {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Truecolor),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::TruecolorAlpha),
+            _ => Err(Error::InvalidImage(format!("unknown color type {}", id))),
+        }
+    }
+
+    /// Number of samples in each pixel.
+    pub fn channel_count(self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Truecolor => 3,
+            ColorType::TruecolorAlpha => 4,
+        }
+    }
+
+    /// The bit depths the PNG spec allows with this color type.
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Truecolor | ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => {
+                &[8, 16]
+            }
+        }
+    }
+
+    /// Whether pixels carry their own alpha sample.
+    pub fn has_alpha(self) -> bool {
+        matches!(self, ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha)
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Truecolor => "truecolor",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::TruecolorAlpha => "truecolor with alpha",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The image header: geometry and color model, from the `IHDR` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per sample, or per palette index for indexed images.
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0 (zlib deflate) in valid files.
+    pub compression_method: u8,
+    /// Always 0 (adaptive filtering) in valid files.
+    pub filter_method: u8,
+    /// Whether scanlines are stored in Adam7 order instead of top to bottom.
+    pub interlaced: bool,
+}
+
+impl Ihdr {
+    /// `IHDR` data is always 13 bytes.
+    pub const LENGTH: usize = 13;
+
+    /// Largest width or height the PNG spec allows.
+    pub const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+    /// Checks the fields against the PNG spec, including that the bit depth
+    /// is allowed with the color type.
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [("width", self.width), ("height", self.height)] {
+            if value == 0 || value > Ihdr::MAX_DIMENSION {
+                return Err(Error::InvalidImage(format!(
+                    "{} must be 1 to {}, found {}",
+                    name,
+                    Ihdr::MAX_DIMENSION,
+                    value
+                )));
+            }
+        }
+
+        if !self.color_type.allowed_bit_depths().contains(&self.bit_depth) {
+            return Err(Error::InvalidImage(format!(
+                "bit depth {} is not allowed with color type {} ({})",
+                self.bit_depth,
+                self.color_type.id(),
+                self.color_type
+            )));
+        }
+
+        if self.compression_method != 0 {
+            return Err(Error::InvalidImage(format!(
+                "unknown compression method {}",
+                self.compression_method
+            )));
+        }
+
+        if self.filter_method != 0 {
+            return Err(Error::InvalidImage(format!(
+                "unknown filter method {}",
+                self.filter_method
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Bits in each pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channel_count() * self.bit_depth as usize
+    }
+
+    /// Serializes the header as `IHDR` chunk data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Ihdr::LENGTH);
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.push(self.bit_depth);
+        bytes.push(self.color_type.id());
+        bytes.push(self.compression_method);
+        bytes.push(self.filter_method);
+        bytes.push(self.interlaced as u8);
+        bytes
+    }
+
+    /// Builds the `IHDR` chunk for this header.
+    pub fn to_chunk(&self) -> Chunk {
+        let chunk_type = ChunkType::try_from(*b"IHDR").expect("IHDR is a valid chunk type");
+        Chunk::new(chunk_type, self.to_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != Ihdr::LENGTH {
+            return Err(Error::InvalidImage(format!(
+                "IHDR must be {} bytes, found {}",
+                Ihdr::LENGTH,
+                data.len()
+            )));
+        }
+
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            method => {
+                return Err(Error::InvalidImage(format!(
+                    "unknown interlace method {}",
+                    method
+                )))
+            }
+        };
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: ColorType::from_id(data[9])?,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlaced,
+        };
+
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().bytes() != *b"IHDR" {
+            return Err(Error::InvalidImage(format!(
+                "expected an IHDR chunk, found {}",
+                chunk.chunk_type()
+            )));
+        }
+
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Ihdr {{")?;
+        writeln!(f, "  Size: {}x{}", self.width, self.height)?;
+        writeln!(f, "  Bit depth: {}", self.bit_depth)?;
+        writeln!(f, "  Color type: {} ({})", self.color_type.id(), self.color_type)?;
+        writeln!(f, "  Compression: {}", self.compression_method)?;
+        writeln!(f, "  Filter: {}", self.filter_method)?;
+        writeln!(
+            f,
+            "  Interlace: {}",
+            if self.interlaced { "Adam7" } else { "none" }
+        )?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_ihdr() -> Ihdr {
+        Ihdr {
+            width: 640,
+            height: 480,
+            bit_depth: 8,
+            color_type: ColorType::TruecolorAlpha,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: true,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ihdr = testing_ihdr();
+        let bytes = ihdr.to_bytes();
+
+        assert_eq!(bytes.len(), Ihdr::LENGTH);
+        assert_eq!(Ihdr::try_from(&bytes[..]).unwrap(), ihdr);
+        assert_eq!(Ihdr::try_from(&ihdr.to_chunk()).unwrap(), ihdr);
+    }
+
+    #[test]
+    fn test_allowed_combinations() {
+        for (color_type, bit_depth, valid) in [
+            (ColorType::Grayscale, 1, true),
+            (ColorType::Grayscale, 16, true),
+            (ColorType::Indexed, 8, true),
+            (ColorType::Indexed, 16, false),
+            (ColorType::Truecolor, 4, false),
+            (ColorType::GrayscaleAlpha, 8, true),
+            (ColorType::TruecolorAlpha, 2, false),
+        ] {
+            let ihdr = Ihdr {
+                color_type,
+                bit_depth,
+                ..testing_ihdr()
+            };
+            assert_eq!(ihdr.validate().is_ok(), valid, "{} at {} bits", color_type, bit_depth);
+        }
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let mut bytes = testing_ihdr().to_bytes();
+        bytes[9] = 5;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_ihdr().to_bytes();
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_ihdr().to_bytes();
+        bytes[0..4].copy_from_slice(&0u32.to_be_bytes());
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let bytes = testing_ihdr().to_bytes();
+        assert!(Ihdr::try_from(&bytes[..12]).is_err());
+    }
+
+    #[test]
+    fn test_bits_per_pixel() {
+        assert_eq!(testing_ihdr().bits_per_pixel(), 32);
+
+        let indexed = Ihdr {
+            color_type: ColorType::Indexed,
+            bit_depth: 4,
+            ..testing_ihdr()
+        };
+        assert_eq!(indexed.bits_per_pixel(), 4);
+    }
+}
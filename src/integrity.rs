@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::FromStr;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// The chunk the manifest is stored in. Ancillary and private, and unsafe
+/// to copy, since it describes the critical chunks.
+pub const CHUNK_TYPE: &str = "psHA";
+
+/// Marks chunk data produced by [`Manifest::to_chunk`].
+const MAGIC: [u8; 4] = *b"PSIH";
+const VERSION: u8 = 1;
+
+/// Magic, version and digest.
+const LENGTH: usize = MAGIC.len() + 1 + Digest::LENGTH;
+
+/// A SHA-256 hash of the image data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest(pub [u8; 32]);
+
+impl Digest {
+    const LENGTH: usize = 32;
+
+    /// Hashes what defines the image: `IHDR`, `PLTE` if there is one, and
+    /// the `IDAT` data joined into one stream, so splitting it differently
+    /// over chunks doesn't count as a change. Ancillary chunks are left out.
+    pub fn of(png: &Png) -> Result<Digest> {
+        let chunks = png.chunks();
+        let ihdr = match chunks.first() {
+            Some(chunk) if chunk.chunk_type().bytes() == *b"IHDR" => chunk,
+            _ => return Err(Error::InvalidImage("IHDR is not the first chunk".to_string())),
+        };
+
+        // Each part is hashed with its type and length, so bytes can't move
+        // from one part to the next without changing the digest
+        let mut hasher = Sha256::new();
+        for chunk in [Some(ihdr), png.chunk_by_type("PLTE")].into_iter().flatten() {
+            hasher.update(chunk.chunk_type().bytes());
+            hasher.update((chunk.length() as u64).to_be_bytes());
+            hasher.update(chunk.data());
+        }
+
+        let idat: Vec<&[u8]> = chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .map(Chunk::data)
+            .collect();
+        hasher.update(b"IDAT");
+        hasher.update(idat.iter().map(|data| data.len() as u64).sum::<u64>().to_be_bytes());
+        idat.iter().for_each(|data| hasher.update(data));
+
+        Ok(Digest(hasher.finalize().into()))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// The digest of the image data as it was when the manifest was stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Manifest {
+    pub digest: Digest,
+}
+
+impl Manifest {
+    /// A manifest of `png` as it is now.
+    pub fn new(png: &Png) -> Result<Manifest> {
+        Ok(Manifest {
+            digest: Digest::of(png)?,
+        })
+    }
+
+    /// Reads the manifest stored in `png`.
+    pub fn find(png: &Png) -> Result<Manifest> {
+        png.chunk_by_type(CHUNK_TYPE)
+            .ok_or_else(|| Error::ChunkNotFound(CHUNK_TYPE.to_string()))
+            .and_then(Manifest::try_from)
+    }
+
+    /// Recomputes the digest of `png` and compares it with the stored one,
+    /// failing with [`Error::IntegrityMismatch`] if they differ.
+    pub fn check(&self, png: &Png) -> Result<()> {
+        let found = Digest::of(png)?;
+        if found != self.digest {
+            return Err(Error::IntegrityMismatch {
+                expected: self.digest.to_string(),
+                found: found.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the manifest as a [`CHUNK_TYPE`] chunk.
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(LENGTH);
+        data.extend_from_slice(&MAGIC);
+        data.push(VERSION);
+        data.extend_from_slice(&self.digest.0);
+
+        let chunk_type = ChunkType::from_str(CHUNK_TYPE).expect("psHA is a valid chunk type");
+        Chunk::new(chunk_type, data)
+    }
+}
+
+impl TryFrom<&Chunk> for Manifest {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if chunk.chunk_type().to_string() != CHUNK_TYPE || !data.starts_with(&MAGIC) {
+            return Err(Error::InvalidPayload("chunk is not an integrity manifest".to_string()));
+        }
+
+        if data.len() != LENGTH {
+            return Err(Error::InvalidPayload(format!(
+                "integrity manifest must be {} bytes, found {}",
+                LENGTH,
+                data.len()
+            )));
+        }
+
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::InvalidPayload(format!(
+                "unsupported integrity manifest version {}",
+                version
+            )));
+        }
+
+        let mut digest = [0; Digest::LENGTH];
+        digest.copy_from_slice(&data[MAGIC.len() + 1..]);
+        Ok(Manifest {
+            digest: Digest(digest),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png(idat: &[&[u8]]) -> Png {
+        let mut chunks = vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0]),
+            chunk("PLTE", &[0, 0, 0]),
+        ];
+        chunks.extend(idat.iter().map(|data| chunk("IDAT", data)));
+        chunks.push(chunk("IEND", b""));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_ancillary_changes_ignored() {
+        let png = testing_png(&[b"image data"]);
+        let manifest = Manifest::new(&png).unwrap();
+
+        let mut edited = testing_png(&[b"image data"]);
+        edited.append_chunk(chunk("tEXt", b"Comment\0added later"));
+        edited.append_chunk(manifest.to_chunk());
+        manifest.check(&edited).unwrap();
+        assert_eq!(Manifest::find(&edited).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_idat_split_ignored() {
+        let joined = Digest::of(&testing_png(&[b"image data"])).unwrap();
+        let split = Digest::of(&testing_png(&[b"image", b" data"])).unwrap();
+
+        assert_eq!(joined, split);
+    }
+
+    #[test]
+    fn test_critical_changes_detected() {
+        let manifest = Manifest::new(&testing_png(&[b"image data"])).unwrap();
+
+        assert!(matches!(
+            manifest.check(&testing_png(&[b"image dat4"])),
+            Err(Error::IntegrityMismatch { .. })
+        ));
+
+        let mut repaletted = testing_png(&[b"image data"]);
+        repaletted.remove_chunk("PLTE").unwrap();
+        assert!(manifest.check(&repaletted).is_err());
+    }
+
+    #[test]
+    fn test_missing_manifest() {
+        assert!(matches!(
+            Manifest::find(&testing_png(&[b"image data"])),
+            Err(Error::ChunkNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let manifest = Manifest::new(&testing_png(&[b"image data"])).unwrap();
+        let chunk = manifest.to_chunk();
+
+        assert!(!chunk.chunk_type().is_safe_to_copy());
+        assert_eq!(Manifest::try_from(&chunk).unwrap(), manifest);
+    }
+}
@@ -0,0 +1,48 @@
+//! Read, edit and write the chunks of a PNG file.
+//!
+//! A [`Png`] is the 8-byte signature followed by a list of [`Chunk`]s, each
+//! tagged with a four-letter [`ChunkType`]. Parsing checks the signature and
+//! every chunk's length and CRC; serializing writes the same bytes back.
+//!
+//! Large files can be processed a chunk at a time with [`ChunkReader`] and
+//! [`ChunkWriter`] instead of loading them into a [`Png`].
+//!
+//! ```
+//! use std::str::FromStr;
+//! use png_secret::{Chunk, ChunkType, Png};
+//!
+//! let mut png = Png::from_chunks(Vec::new());
+//! let chunk_type = ChunkType::from_str("RuSt")?;
+//! png.append_chunk(Chunk::new(chunk_type, b"hidden".to_vec()));
+//!
+//! let bytes = png.as_bytes();
+//! let parsed = Png::try_from(bytes.as_ref())?;
+//! assert_eq!(parsed.chunk_by_type("RuSt").unwrap().data(), b"hidden");
+//! # Ok::<(), png_secret::Error>(())
+//! ```
+
+pub mod attachment;
+mod chunk;
+mod chunk_type;
+pub mod compress;
+pub mod crypto;
+mod error;
+mod ihdr;
+pub mod integrity;
+pub mod lsb;
+pub mod payload;
+mod png;
+pub mod report;
+pub mod scan;
+pub mod signature;
+pub mod split;
+mod stream;
+pub mod text;
+pub mod validate;
+
+pub use chunk::Chunk;
+pub use chunk_type::{ChunkType, ChunkTypeError};
+pub use error::{Error, Result};
+pub use ihdr::{ColorType, Ihdr};
+pub use png::Png;
+pub use stream::{ChunkHeader, ChunkReader, ChunkWriter};
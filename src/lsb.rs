@@ -0,0 +1,658 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Bytes of framing ahead of the payload: a chunk type used as a tag, then
+/// the payload length.
+const FRAME_LENGTH: usize = 8;
+
+/// Maximum data per rewritten `IDAT` chunk.
+const IDAT_CHUNK_SIZE: usize = 1 << 16;
+
+/// Adam7 passes as (x start, y start, x step, y step).
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Which color channels carry payload bits. Grayscale samples are used
+/// when any of red, green or blue is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels {
+            red: true,
+            green: true,
+            blue: true,
+            alpha: false,
+        }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = Error;
+
+    /// Parses any combination of the letters `r`, `g`, `b` and `a`.
+    fn from_str(string: &str) -> Result<Self> {
+        let mut channels = Channels {
+            red: false,
+            green: false,
+            blue: false,
+            alpha: false,
+        };
+
+        for letter in string.chars() {
+            match letter.to_ascii_lowercase() {
+                'r' => channels.red = true,
+                'g' => channels.green = true,
+                'b' => channels.blue = true,
+                'a' => channels.alpha = true,
+                _ => {
+                    return Err(Error::InvalidArgument(format!(
+                        "unknown channel '{}', expected r, g, b or a",
+                        letter
+                    )))
+                }
+            }
+        }
+
+        if string.is_empty() {
+            return Err(Error::InvalidArgument(
+                "at least one channel is required".to_string(),
+            ));
+        }
+
+        Ok(channels)
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (selected, letter) in [
+            (self.red, 'r'),
+            (self.green, 'g'),
+            (self.blue, 'b'),
+            (self.alpha, 'a'),
+        ] {
+            if selected {
+                write!(f, "{}", letter)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of payload bytes that fit in the least-significant bits of the
+/// selected channels.
+pub fn capacity(png: &Png, channels: Channels) -> Result<usize> {
+    let layout = Layout::from_png(png)?;
+    Ok(layout.carrier_count(channels)?.saturating_sub(FRAME_LENGTH * 8) / 8)
+}
+
+/// Hides `payload` in the least-significant bit of each selected sample,
+/// tagged with `chunk_type` so [`extract`] can tell it apart from noise.
+///
+/// The image data is decompressed, unfiltered, modified and then filtered
+/// again with each scanline's original filter type. Every other chunk is
+/// copied unchanged.
+pub fn embed(png: &Png, chunk_type: &ChunkType, payload: &[u8], channels: Channels) -> Result<Png> {
+    let layout = Layout::from_png(png)?;
+    let available = layout.carrier_count(channels)?.saturating_sub(FRAME_LENGTH * 8) / 8;
+    if payload.len() > available || payload.len() > u32::MAX as usize {
+        return Err(Error::InsufficientCapacity {
+            needed: payload.len(),
+            available,
+        });
+    }
+
+    let mut pixels = layout.unfilter(&layout.decompress(png)?)?;
+
+    let frame = chunk_type
+        .bytes()
+        .iter()
+        .chain((payload.len() as u32).to_be_bytes().iter())
+        .chain(payload.iter())
+        .copied()
+        .collect::<Vec<u8>>();
+    let bits = frame
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+
+    for (offset, bit) in layout.carriers(channels).zip(bits) {
+        pixels[offset] = (pixels[offset] & !1) | bit;
+    }
+
+    let image_data = layout.compress(&layout.filter(&pixels))?;
+    replace_image_data(png, &image_data)
+}
+
+/// Reads a payload written by [`embed`] with the same tag and channels.
+pub fn extract(png: &Png, chunk_type: &ChunkType, channels: Channels) -> Result<Vec<u8>> {
+    let layout = Layout::from_png(png)?;
+    let pixels = layout.unfilter(&layout.decompress(png)?)?;
+
+    let mut bits = layout.carriers(channels).map(|offset| pixels[offset] & 1);
+    let mut next_byte = || {
+        (0..8).try_fold(0u8, |byte, _| bits.next().map(|bit| (byte << 1) | bit))
+    };
+
+    let mut frame = [0; FRAME_LENGTH];
+    for byte in frame.iter_mut() {
+        *byte = next_byte().ok_or(Error::InsufficientCapacity {
+            needed: FRAME_LENGTH,
+            available: 0,
+        })?;
+    }
+
+    if frame[..4] != chunk_type.bytes() {
+        return Err(Error::InvalidPayload(format!(
+            "no LSB payload tagged {} in the selected channels",
+            chunk_type
+        )));
+    }
+
+    let length = u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]) as usize;
+    let available = layout.carrier_count(channels)?.saturating_sub(FRAME_LENGTH * 8) / 8;
+    if length > available {
+        return Err(Error::InvalidPayload(format!(
+            "LSB payload claims {} bytes but only {} fit in the image",
+            length, available
+        )));
+    }
+
+    (0..length)
+        .map(|_| {
+            next_byte().ok_or_else(|| Error::InvalidPayload("LSB payload is truncated".to_string()))
+        })
+        .collect()
+}
+
+/// The low byte of every sample, split by channel and then by row, so
+/// `planes[channel][row][column]`. Rows of an Adam7 image are listed pass
+/// by pass. Steganalysis uses this to look at the bit planes [`embed`]
+/// writes to.
+pub(crate) fn sample_planes(png: &Png) -> Result<Vec<Vec<Vec<u8>>>> {
+    let layout = Layout::from_png(png)?;
+    let pixels = layout.unfilter(&layout.decompress(png)?)?;
+    let bytes_per_pixel = layout.bytes_per_pixel();
+    let bytes_per_sample = layout.bytes_per_sample();
+
+    let mut planes = vec![Vec::new(); layout.channel_count()];
+    let mut offset = 0;
+    for (width, height) in layout.passes() {
+        let stride = 1 + width * bytes_per_pixel;
+        for row in 0..height {
+            let start = offset + row * stride + 1;
+            for (channel, plane) in planes.iter_mut().enumerate() {
+                let low_byte = start + (channel + 1) * bytes_per_sample - 1;
+                plane.push(
+                    (0..width)
+                        .map(|column| pixels[low_byte + column * bytes_per_pixel])
+                        .collect(),
+                );
+            }
+        }
+        offset += stride * height;
+    }
+
+    Ok(planes)
+}
+
+/// Swaps every `IDAT` chunk for new ones holding `image_data`, placed where
+/// the first `IDAT` was. Data after `IEND` is kept.
+fn replace_image_data(png: &Png, image_data: &[u8]) -> Result<Png> {
+    let idat_type = ChunkType::try_from(*b"IDAT")?;
+    let mut idats = image_data
+        .chunks(IDAT_CHUNK_SIZE)
+        .map(|data| Chunk::new(idat_type.clone(), data.to_vec()));
+
+    let mut chunks = Vec::with_capacity(png.chunks().len());
+    for chunk in png.chunks() {
+        if *chunk.chunk_type() == idat_type {
+            chunks.extend(&mut idats);
+        } else {
+            chunks.push(chunk.clone());
+        }
+    }
+
+    let mut result = Png::from_chunks(chunks);
+    result.set_trailer(png.trailer().to_vec())?;
+    Ok(result)
+}
+
+/// The image header, checked to be something whose samples can be walked
+/// byte by byte: 8- or 16-bit, without a palette.
+struct Layout {
+    ihdr: Ihdr,
+    width: usize,
+    height: usize,
+}
+
+impl Layout {
+    fn from_png(png: &Png) -> Result<Layout> {
+        let ihdr = png.ihdr()?;
+
+        if ihdr.color_type == ColorType::Indexed {
+            return Err(Error::UnsupportedImage(format!(
+                "color type {} has no direct samples to hide data in",
+                ihdr.color_type.id()
+            )));
+        }
+
+        if !matches!(ihdr.bit_depth, 8 | 16) {
+            return Err(Error::UnsupportedImage(format!(
+                "bit depth {} is not supported, only 8 and 16",
+                ihdr.bit_depth
+            )));
+        }
+
+        Ok(Layout {
+            ihdr,
+            width: ihdr.width as usize,
+            height: ihdr.height as usize,
+        })
+    }
+
+    fn channel_count(&self) -> usize {
+        self.ihdr.color_type.channel_count()
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        self.ihdr.bit_depth as usize / 8
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.channel_count() * self.bytes_per_sample()
+    }
+
+    /// Indices of the selected channels within a pixel.
+    fn selected_channels(&self, channels: Channels) -> Vec<usize> {
+        let gray = channels.red || channels.green || channels.blue;
+        let selected: &[bool] = match self.ihdr.color_type {
+            ColorType::Grayscale | ColorType::Indexed => &[gray],
+            ColorType::Truecolor => &[channels.red, channels.green, channels.blue],
+            ColorType::GrayscaleAlpha => &[gray, channels.alpha],
+            ColorType::TruecolorAlpha => {
+                &[channels.red, channels.green, channels.blue, channels.alpha]
+            }
+        };
+
+        (0..selected.len()).filter(|&index| selected[index]).collect()
+    }
+
+    /// Width and height of each non-empty pass; a single pass unless the
+    /// image is Adam7-interlaced.
+    fn passes(&self) -> Vec<(usize, usize)> {
+        if !self.ihdr.interlaced {
+            return vec![(self.width, self.height)];
+        }
+
+        ADAM7
+            .iter()
+            .map(|&(x, y, dx, dy)| {
+                (
+                    (self.width + dx - 1 - x) / dx,
+                    (self.height + dy - 1 - y) / dy,
+                )
+            })
+            .filter(|&(width, height)| width > 0 && height > 0)
+            .collect()
+    }
+
+    /// Size of the decompressed image data, including filter bytes.
+    fn raw_size(&self) -> Result<usize> {
+        self.passes().iter().try_fold(0usize, |total, &(width, height)| {
+            width
+                .checked_mul(self.bytes_per_pixel())
+                .and_then(|row| row.checked_add(1))
+                .and_then(|row| row.checked_mul(height))
+                .and_then(|pass| pass.checked_add(total))
+                .ok_or_else(|| Error::UnsupportedImage("image is too large".to_string()))
+        })
+    }
+
+    fn carrier_count(&self, channels: Channels) -> Result<usize> {
+        let pixels = self
+            .passes()
+            .iter()
+            .map(|&(width, height)| width * height)
+            .sum::<usize>();
+
+        pixels
+            .checked_mul(self.selected_channels(channels).len())
+            .ok_or_else(|| Error::UnsupportedImage("image is too large".to_string()))
+    }
+
+    /// Offsets, into the unfiltered image data, of the byte holding the
+    /// least-significant bit of every selected sample, in scanline order.
+    fn carriers(&self, channels: Channels) -> impl Iterator<Item = usize> {
+        let selected = self.selected_channels(channels);
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let bytes_per_sample = self.bytes_per_sample();
+
+        let mut rows = Vec::new();
+        let mut offset = 0;
+        for (width, height) in self.passes() {
+            let stride = 1 + width * bytes_per_pixel;
+            for _ in 0..height {
+                rows.push((offset + 1, width));
+                offset += stride;
+            }
+        }
+
+        rows.into_iter().flat_map(move |(start, width)| {
+            let selected = selected.clone();
+            (0..width).flat_map(move |pixel| {
+                let pixel_start = start + pixel * bytes_per_pixel;
+                selected
+                    .clone()
+                    .into_iter()
+                    .map(move |channel| pixel_start + (channel + 1) * bytes_per_sample - 1)
+            })
+        })
+    }
+
+    fn decompress(&self, png: &Png) -> Result<Vec<u8>> {
+        let compressed: Vec<u8> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+
+        let expected = self.raw_size()?;
+        let mut raw = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(expected as u64 + 1)
+            .read_to_end(&mut raw)
+            .map_err(|err| Error::InvalidImage(format!("image data is corrupt: {}", err)))?;
+
+        if raw.len() != expected {
+            return Err(Error::InvalidImage(format!(
+                "image data is {} bytes once decompressed, IHDR implies {}",
+                raw.len(),
+                expected
+            )));
+        }
+
+        Ok(raw)
+    }
+
+    fn compress(&self, raw: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Undoes scanline filtering, leaving each row's filter type byte in
+    /// place so [`Layout::filter`] can reapply it.
+    fn unfilter(&self, raw: &[u8]) -> Result<Vec<u8>> {
+        let bpp = self.bytes_per_pixel();
+        let mut pixels = raw.to_vec();
+        let mut offset = 0;
+
+        for (width, height) in self.passes() {
+            let stride = 1 + width * bpp;
+            for row in 0..height {
+                let start = offset + row * stride;
+                let (before, current) = pixels.split_at_mut(start);
+                let prior = if row == 0 {
+                    None
+                } else {
+                    Some(&before[start - stride + 1..])
+                };
+
+                let filter = current[0];
+                let line = &mut current[1..stride];
+                for i in 0..line.len() {
+                    let left = if i >= bpp { line[i - bpp] } else { 0 };
+                    let up = prior.map_or(0, |prior| prior[i]);
+                    let up_left = match prior {
+                        Some(prior) if i >= bpp => prior[i - bpp],
+                        _ => 0,
+                    };
+
+                    line[i] = line[i].wrapping_add(predict(filter, left, up, up_left)?);
+                }
+            }
+
+            offset += stride * height;
+        }
+
+        Ok(pixels)
+    }
+
+    /// Filters unfiltered rows using the filter type byte stored ahead of
+    /// each one.
+    fn filter(&self, pixels: &[u8]) -> Vec<u8> {
+        let bpp = self.bytes_per_pixel();
+        let mut raw = pixels.to_vec();
+        let mut offset = 0;
+
+        for (width, height) in self.passes() {
+            let stride = 1 + width * bpp;
+            for row in 0..height {
+                let start = offset + row * stride;
+                let line = &pixels[start + 1..start + stride];
+                let prior = if row == 0 {
+                    None
+                } else {
+                    Some(&pixels[start - stride + 1..start])
+                };
+
+                let filter = pixels[start];
+                for i in 0..line.len() {
+                    let left = if i >= bpp { line[i - bpp] } else { 0 };
+                    let up = prior.map_or(0, |prior| prior[i]);
+                    let up_left = match prior {
+                        Some(prior) if i >= bpp => prior[i - bpp],
+                        _ => 0,
+                    };
+
+                    // The filter type was validated while unfiltering
+                    let prediction = predict(filter, left, up, up_left).unwrap_or(0);
+                    raw[start + 1 + i] = line[i].wrapping_sub(prediction);
+                }
+            }
+
+            offset += stride * height;
+        }
+
+        raw
+    }
+}
+
+/// The value a scanline filter predicts for a byte from its neighbours.
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> Result<u8> {
+    match filter {
+        0 => Ok(0),
+        1 => Ok(left),
+        2 => Ok(up),
+        3 => Ok(((left as u16 + up as u16) / 2) as u8),
+        4 => {
+            let estimate = left as i16 + up as i16 - up_left as i16;
+            let distance_left = (estimate - left as i16).abs();
+            let distance_up = (estimate - up as i16).abs();
+            let distance_up_left = (estimate - up_left as i16).abs();
+
+            if distance_left <= distance_up && distance_left <= distance_up_left {
+                Ok(left)
+            } else if distance_up <= distance_up_left {
+                Ok(up)
+            } else {
+                Ok(up_left)
+            }
+        }
+        _ => Err(Error::InvalidImage(format!(
+            "unknown scanline filter type {}",
+            filter
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an image whose rows cycle through all five filter types.
+    fn testing_png(width: u32, height: u32, bit_depth: u8, color_type: u8, interlaced: bool) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, interlaced as u8]);
+
+        let ihdr_chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr);
+        let mut png = Png::from_chunks(vec![ihdr_chunk]);
+        let layout = Layout::from_png(&png).unwrap();
+
+        let mut pixels = Vec::new();
+        let mut value = 7u8;
+        for (width, height) in layout.passes() {
+            for row in 0..height {
+                pixels.push((row % 5) as u8);
+                for _ in 0..width * layout.bytes_per_pixel() {
+                    value = value.wrapping_mul(31).wrapping_add(17);
+                    pixels.push(value);
+                }
+            }
+        }
+
+        let image_data = layout.compress(&layout.filter(&pixels)).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::from_str("IDAT").unwrap(), image_data));
+        png.append_chunk(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        png
+    }
+
+    fn pixels(png: &Png) -> Vec<u8> {
+        let layout = Layout::from_png(png).unwrap();
+        layout.unfilter(&layout.decompress(png).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_rgb8() {
+        let png = testing_png(16, 9, 8, 2, false);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+
+        let embedded = embed(&png, &tag, b"hidden in plain sight", Channels::default()).unwrap();
+        let message = extract(&embedded, &tag, Channels::default()).unwrap();
+
+        assert_eq!(message, b"hidden in plain sight");
+    }
+
+    #[test]
+    fn test_round_trip_rgba16() {
+        let png = testing_png(8, 8, 16, 6, false);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        let channels = Channels::from_str("rgba").unwrap();
+
+        let embedded = embed(&png, &tag, b"sixteen bits", channels).unwrap();
+        assert_eq!(extract(&embedded, &tag, channels).unwrap(), b"sixteen bits");
+    }
+
+    #[test]
+    fn test_round_trip_interlaced_gray() {
+        let png = testing_png(13, 11, 8, 0, true);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+
+        let embedded = embed(&png, &tag, b"adam7", Channels::default()).unwrap();
+        assert_eq!(extract(&embedded, &tag, Channels::default()).unwrap(), b"adam7");
+    }
+
+    #[test]
+    fn test_trailer_kept() {
+        let mut png = testing_png(8, 8, 8, 2, false);
+        png.set_trailer(b"appended".to_vec()).unwrap();
+        let tag = ChunkType::from_str("RuSt").unwrap();
+
+        let embedded = embed(&png, &tag, b"lsb", Channels::default()).unwrap();
+        assert_eq!(embedded.trailer(), b"appended");
+    }
+
+    #[test]
+    fn test_only_least_significant_bits_change() {
+        let png = testing_png(16, 9, 8, 6, false);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        let embedded = embed(&png, &tag, b"only the lsb", Channels::default()).unwrap();
+
+        let before = pixels(&png);
+        let after = pixels(&embedded);
+        assert_eq!(before.len(), after.len());
+
+        for (index, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+            let is_alpha = index % (1 + 16 * 4) != 0 && (index % (1 + 16 * 4) - 1) % 4 == 3;
+            if is_alpha {
+                assert_eq!(old, new);
+            } else {
+                assert_eq!(old & !1, new & !1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(8, 8, 8, 2, false);
+        assert_eq!(capacity(&png, Channels::default()).unwrap(), 8 * 8 * 3 / 8 - 8);
+
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        assert!(embed(&png, &tag, &[0; 16], Channels::default()).is_ok());
+        assert!(matches!(
+            embed(&png, &tag, &[0; 17], Channels::default()),
+            Err(Error::InsufficientCapacity { needed: 17, available: 16 })
+        ));
+    }
+
+    #[test]
+    fn test_wrong_tag() {
+        let png = testing_png(16, 9, 8, 2, false);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        let embedded = embed(&png, &tag, b"message", Channels::default()).unwrap();
+
+        let other = ChunkType::from_str("RuSa").unwrap();
+        assert!(extract(&embedded, &other, Channels::default()).is_err());
+    }
+
+    #[test]
+    fn test_palette_unsupported() {
+        let png = testing_png(4, 4, 8, 2, false);
+        let mut chunks = png.chunks().to_vec();
+        let mut ihdr = chunks[0].data().to_vec();
+        ihdr[9] = 3;
+        chunks[0] = Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr);
+
+        assert!(matches!(
+            capacity(&Png::from_chunks(chunks), Channels::default()),
+            Err(Error::UnsupportedImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_channels() {
+        let channels = Channels::from_str("ga").unwrap();
+        assert!(!channels.red && channels.green && !channels.blue && channels.alpha);
+        assert_eq!(channels.to_string(), "ga");
+        assert!(Channels::from_str("rx").is_err());
+    }
+}
@@ -1,12 +1,52 @@
+use std::error::Error as _;
+use std::io;
+use std::process::ExitCode;
+
+use clap::Parser;
+use png_secret::{Error, Result};
+
+use crate::args::{Cli, Command};
+
 mod args;
-mod chunk;
-mod chunk_type;
+mod batch;
 mod commands;
-mod png;
+mod stdio;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        // Whatever reads the output stopped early, as `head` does
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+
+            let mut source = err.source();
+            while let Some(cause) = source {
+                eprintln!("  caused by: {}", cause);
+                source = cause.source();
+            }
+
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
 
-fn main() -> Result<()> {
-    todo!()
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Encode(args) => commands::encode(args),
+        Command::Decode(args) => commands::decode(args),
+        Command::Remove(args) => commands::remove(args),
+        Command::Print(args) => commands::print(args),
+        Command::Text(args) => commands::text(args),
+        Command::Validate(args) => commands::validate(args),
+        Command::Trailer(args) => commands::trailer(args),
+        Command::Scan(args) => commands::scan(args),
+        Command::Sign(args) => commands::sign(args),
+        Command::Verify(args) => commands::verify(args),
+        Command::Keygen(args) => commands::keygen(args),
+        Command::AddIntegrity(args) => commands::add_integrity(args),
+        Command::CheckIntegrity(args) => commands::check_integrity(args),
+    }
 }
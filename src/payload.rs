@@ -0,0 +1,140 @@
+use crate::{Error, Result};
+
+/// Marks data produced by [`wrap`].
+const MAGIC: [u8; 4] = *b"PSPY";
+const VERSION: u8 = 1;
+
+/// Magic, version and flags.
+pub const HEADER_LENGTH: usize = MAGIC.len() + 1 + 1;
+
+/// Flag set when the body was encrypted with [`crate::crypto::encrypt`].
+const ENCRYPTED: u8 = 1 << 0;
+
+/// Flag set when the message is a file wrapped by
+/// [`crate::attachment::Attachment::to_bytes`].
+const ATTACHMENT: u8 = 1 << 1;
+
+/// Every flag this version knows; others are rejected rather than ignored.
+const KNOWN_FLAGS: u8 = ENCRYPTED | ATTACHMENT;
+
+/// What the decrypted, decompressed message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Kind {
+    /// Bytes to print or write out as they are.
+    #[default]
+    Message,
+    /// A file with its name and MIME type, to restore with
+    /// [`crate::attachment::Attachment`]. The kind is readable without the
+    /// passphrase; the name and MIME type are not.
+    Attachment,
+}
+
+/// The header in front of every hidden payload. It says how to read the
+/// body that follows, so decoding never guesses from the body's first bytes,
+/// which may be anything the user chose to hide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Envelope {
+    /// Whether the body must be decrypted before it is decompressed.
+    pub encrypted: bool,
+    pub kind: Kind,
+}
+
+impl Envelope {
+    fn flags(self) -> u8 {
+        let mut flags = 0;
+        if self.encrypted {
+            flags |= ENCRYPTED;
+        }
+        if self.kind == Kind::Attachment {
+            flags |= ATTACHMENT;
+        }
+        flags
+    }
+}
+
+/// Puts `body` behind the header describing it.
+pub fn wrap(envelope: Envelope, body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LENGTH + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    bytes.push(envelope.flags());
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// Reads the header written by [`wrap`], returning it and the body.
+pub fn unwrap(data: &[u8]) -> Result<(Envelope, &[u8])> {
+    if !is_envelope(data) {
+        return Err(Error::InvalidPayload("data is not a png-secret payload".to_string()));
+    }
+
+    if data.len() < HEADER_LENGTH {
+        return Err(Error::InvalidPayload("payload header is truncated".to_string()));
+    }
+
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::InvalidPayload(format!(
+            "unsupported payload version {}",
+            version
+        )));
+    }
+
+    let flags = data[MAGIC.len() + 1];
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(Error::InvalidPayload(format!("unknown payload flags {:#04x}", flags)));
+    }
+
+    let envelope = Envelope {
+        encrypted: flags & ENCRYPTED != 0,
+        kind: if flags & ATTACHMENT != 0 {
+            Kind::Attachment
+        } else {
+            Kind::Message
+        },
+    };
+    Ok((envelope, &data[HEADER_LENGTH..]))
+}
+
+/// Whether data starts with the header written by [`wrap`].
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for encrypted in [false, true] {
+            for kind in [Kind::Message, Kind::Attachment] {
+                let envelope = Envelope { encrypted, kind };
+                let bytes = wrap(envelope, b"body");
+
+                assert!(is_envelope(&bytes));
+                assert_eq!(unwrap(&bytes).unwrap(), (envelope, b"body".as_ref()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_body_is_not_interpreted() {
+        // A plain body that happens to start like an encrypted one or a file
+        for body in [b"PSEC hello", b"PSFL hello"] {
+            let bytes = wrap(Envelope::default(), body);
+            let (envelope, read) = unwrap(&bytes).unwrap();
+            assert_eq!(envelope, Envelope::default());
+            assert_eq!(read, body);
+        }
+    }
+
+    #[test]
+    fn test_unknown_flags() {
+        let mut bytes = wrap(Envelope::default(), b"body");
+        bytes[MAGIC.len() + 1] = 0x80;
+
+        assert!(unwrap(&bytes).is_err());
+        assert!(unwrap(&bytes[..HEADER_LENGTH - 1]).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::fmt;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::stream::ChunkReader;
+use crate::{Error, Result};
+
+/// Public ancillary chunks from the PNG spec and its registered extensions.
+/// They are kept through LSB embedding, which leaves the header and palette
+/// alone and moves each sample by at most one.
+pub(crate) const KNOWN_ANCILLARY: [&[u8; 4]; 29] = [
+    b"acTL", b"bKGD", b"cHRM", b"cICP", b"cLLI", b"dSIG", b"eXIf", b"fcTL", b"fdAT", b"fRAc",
+    b"gAMA", b"gIFg", b"gIFt", b"gIFx", b"hIST", b"iCCP", b"iTXt", b"mDCV", b"oFFs", b"pCAL",
+    b"pHYs", b"sBIT", b"sCAL", b"sPLT", b"sRGB", b"sTER", b"tEXt", b"tIME", b"tRNS",
+];
+
+/// A PNG file: the standard signature followed by its chunks, and any
+/// bytes found after `IEND`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Png {
+    chunks: Vec<Chunk>,
+    trailer: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ChunkReader::new(bytes)?;
+        let chunks = reader.by_ref().collect::<Result<Vec<Chunk>>>()?;
+        let trailer = reader.read_trailer()?;
+
+        Ok(Png { chunks, trailer })
+    }
+}
+
+impl fmt::Display for Png {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in &self.chunks {
+            writeln!(f, "{}", chunk.chunk_type())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Png {
+    /// The 8-byte signature every PNG file starts with.
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    /// Builds an image from chunks, in the order they will be written.
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png {
+            chunks,
+            trailer: Vec::new(),
+        }
+    }
+
+    /// Adds a chunk to the image, keeping it ahead of a trailing `IEND` so
+    /// the file stays readable by other decoders.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        match self.chunks.last() {
+            Some(last) if last.chunk_type().to_string() == "IEND" => {
+                let index = self.chunks.len() - 1;
+                self.chunks.insert(index, chunk);
+            }
+            _ => self.chunks.push(chunk),
+        }
+    }
+
+    /// Removes the first chunk of the given type and returns it.
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        let position = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type);
+
+        match position {
+            Some(index) => Ok(self.chunks.remove(index)),
+            None => Err(Error::ChunkNotFound(chunk_type.to_string())),
+        }
+    }
+
+    /// Removes every chunk `remove` returns true for, and returns how many
+    /// were removed.
+    pub fn remove_chunks<F: FnMut(&Chunk) -> bool>(&mut self, mut remove: F) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(|chunk| !remove(chunk));
+        before - self.chunks.len()
+    }
+
+    /// Whether an editor that changed the critical chunks must drop chunks
+    /// of this type, as the PNG spec requires of ancillary chunks marked
+    /// unsafe to copy that it doesn't know. The registered chunks in
+    /// `KNOWN_ANCILLARY` are known, as are the private types in `also_known`.
+    pub fn must_drop_on_edit(chunk_type: &ChunkType, also_known: &[&str]) -> bool {
+        let known = KNOWN_ANCILLARY.contains(&&chunk_type.bytes())
+            || also_known.contains(&chunk_type.to_string().as_str());
+        !chunk_type.is_critical() && !chunk_type.is_safe_to_copy() && !known
+    }
+
+    /// Removes the chunks [`Png::must_drop_on_edit`] is true for, after the
+    /// critical chunks were changed, and returns them in file order.
+    pub fn drop_unsafe_to_copy(&mut self, also_known: &[&str]) -> Vec<Chunk> {
+        let (dropped, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| Png::must_drop_on_edit(chunk.chunk_type(), also_known));
+        self.chunks = kept;
+        dropped
+    }
+
+    /// The signature written ahead of the chunks.
+    pub fn header(&self) -> &[u8; 8] {
+        &Png::STANDARD_HEADER
+    }
+
+    /// All chunks, in file order.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// The first chunk of the given type, if any.
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// The decoded image header, which must be the first chunk.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type().bytes() == *b"IHDR" => Ihdr::try_from(chunk),
+            _ => Err(Error::InvalidImage("IHDR is not the first chunk".to_string())),
+        }
+    }
+
+    /// Bytes after `IEND`, which decoders ignore.
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Where the trailer starts in the serialized file.
+    pub fn trailer_offset(&self) -> usize {
+        Png::STANDARD_HEADER.len()
+            + self
+                .chunks
+                .iter()
+                .map(|chunk| Chunk::METADATA_LENGTH + chunk.length() as usize)
+                .sum::<usize>()
+    }
+
+    /// Replaces the bytes written after `IEND`. A non-empty trailer needs
+    /// `IEND` to be the last chunk, or it would be read as more chunks.
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) -> Result<()> {
+        let ended = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.chunk_type().bytes() == *b"IEND");
+        if !trailer.is_empty() && !ended {
+            return Err(Error::InvalidImage(
+                "data can only be appended after an IEND chunk".to_string(),
+            ));
+        }
+
+        self.trailer = trailer;
+        Ok(())
+    }
+
+    /// Removes and returns the bytes after `IEND`.
+    pub fn take_trailer(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailer)
+    }
+
+    /// Serializes the signature, every chunk and the trailer.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(self.chunks.iter().flat_map(Chunk::as_bytes))
+            .chain(self.trailer.iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(testing_chunks())
+    }
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        let data: Vec<u8> = data.bytes().collect();
+
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let chunks = testing_chunks();
+        let png = Png::from_chunks(chunks);
+
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_valid_from_bytes() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = [13, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(matches!(png, Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        let mut chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        // Truncate the last chunk so its data runs past the end of the file
+        chunk_bytes.truncate(chunk_bytes.len() - 6);
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(matches!(png, Err(Error::TruncatedChunk { .. })));
+    }
+
+    #[test]
+    fn test_list_chunks() {
+        let png = testing_png();
+        let chunks = png.chunks();
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[test]
+    fn test_chunk_by_type() {
+        let png = testing_png();
+        let chunk = png.chunk_by_type("FrSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+    }
+
+    #[test]
+    fn test_append_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+    }
+
+    #[test]
+    fn test_append_chunk_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "TeSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_remove_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.remove_chunk("TeSt").unwrap();
+        let chunk = png.chunk_by_type("TeSt");
+        assert!(chunk.is_none());
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+
+        let removed = png.remove_chunks(|chunk| chunk.chunk_type().to_string() == "TeSt");
+        assert_eq!(removed, 2);
+        assert!(png.chunk_by_type("TeSt").is_none());
+        assert_eq!(png.chunks().len(), testing_chunks().len());
+    }
+
+    #[test]
+    fn test_drop_unsafe_to_copy() {
+        let mut png = testing_png();
+        for chunk_type in ["prIV", "gAMA", "owNH", "saFe"] {
+            png.append_chunk(chunk_from_strings(chunk_type, "data").unwrap());
+        }
+
+        let dropped = png.drop_unsafe_to_copy(&["owNH"]);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].chunk_type().to_string(), "prIV");
+        assert_eq!(png.chunks().len(), testing_chunks().len() + 3);
+        assert!(png.drop_unsafe_to_copy(&["owNH"]).is_empty());
+        assert_eq!(png.drop_unsafe_to_copy(&[]).len(), 1);
+    }
+
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        assert!(matches!(
+            png.remove_chunk("TeSt"),
+            Err(Error::ChunkNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_png_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]);
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let actual = png.as_bytes();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_png_trait_impls() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+
+        let _png_string = format!("{}", png);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"appended");
+
+        let mut png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.trailer(), b"appended");
+        assert_eq!(png.trailer_offset(), PNG_FILE.len());
+        assert_eq!(png.as_bytes(), bytes);
+
+        assert_eq!(png.take_trailer(), b"appended");
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_trailer_needs_iend() {
+        let mut png = testing_png();
+        assert!(png.set_trailer(b"appended".to_vec()).is_err());
+        assert!(png.set_trailer(Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(PNG_FILE.as_ref()).unwrap();
+        let ihdr = png.ihdr().unwrap();
+
+        assert_eq!((ihdr.width, ihdr.height), (1, 1));
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, crate::ColorType::Grayscale);
+        assert!(!ihdr.interlaced);
+        assert!(testing_png().ihdr().is_err());
+    }
+
+    // A 1x1 grayscale image: IHDR, IDAT and IEND with valid CRCs.
+    const PNG_FILE: [u8; 67] = [
+        137, 80, 78, 71, 13, 10, 26, 10,
+        0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0, 58, 126, 155, 85,
+        0, 0, 0, 10, 73, 68, 65, 84, 120, 156, 99, 96, 0, 0, 0, 2, 0, 1, 72, 175, 164, 113,
+        0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+    ];
+}
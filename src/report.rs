@@ -0,0 +1,162 @@
+//! Machine-readable descriptions of chunks, for tools that parse the
+//! output of `--format json` or `--format yaml`.
+//!
+//! # Schema
+//!
+//! Every chunk is described by a [`ChunkInfo`] object:
+//!
+//! | Field          | Type    | Meaning                                          |
+//! |----------------|---------|--------------------------------------------------|
+//! | `offset`       | integer | Byte offset of the chunk's length field          |
+//! | `length`       | integer | Number of data bytes                             |
+//! | `type`         | string  | The four-letter chunk type                       |
+//! | `critical`     | bool    | Whether decoders must understand the chunk       |
+//! | `public`       | bool    | Whether the type is registered or reserved       |
+//! | `safe_to_copy` | bool    | Whether editors may keep it when changing pixels |
+//! | `crc`          | integer | The CRC-32 stored after the data                 |
+//! | `data`         | object  | The data, as a [`Data`] object                   |
+//!
+//! [`Data`] objects have an `encoding` of `"utf8"` or `"base64"` and the
+//! encoded bytes as `value`. Data is given as text when it is valid UTF-8
+//! without control characters other than tab, line feed and carriage
+//! return, and in standard padded base64 otherwise.
+//!
+//! Data after `IEND` is described by a [`TrailerInfo`] object with `offset`,
+//! `length` and `data` fields of the same meaning.
+//!
+//! Fields are never renamed or removed, and their meaning never changes.
+//! New fields may be added, so readers should ignore fields they don't know.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+
+use crate::chunk::Chunk;
+
+/// Bytes encoded for a text format, as UTF-8 when they read as text and as
+/// base64 when they don't.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "encoding", content = "value", rename_all = "lowercase")]
+pub enum Data {
+    Utf8(String),
+    Base64(String),
+}
+
+impl Data {
+    /// Encodes `bytes`, as text if possible.
+    pub fn new(bytes: &[u8]) -> Data {
+        match std::str::from_utf8(bytes) {
+            Ok(text) if !text.chars().any(is_binary_control) => Data::Utf8(text.to_string()),
+            _ => Data::Base64(STANDARD.encode(bytes)),
+        }
+    }
+}
+
+fn is_binary_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r')
+}
+
+/// A chunk and where it sits in its file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkInfo {
+    pub offset: u64,
+    pub length: u32,
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+    pub crc: u32,
+    pub data: Data,
+}
+
+impl ChunkInfo {
+    /// Describes `chunk`, found at `offset` in its file.
+    pub fn new(chunk: &Chunk, offset: u64) -> ChunkInfo {
+        let chunk_type = chunk.chunk_type();
+        ChunkInfo {
+            offset,
+            length: chunk.length(),
+            chunk_type: chunk_type.to_string(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            crc: chunk.crc(),
+            data: Data::new(chunk.data()),
+        }
+    }
+
+    /// Where the chunk after this one starts.
+    pub fn next_offset(&self) -> u64 {
+        self.offset + Chunk::METADATA_LENGTH as u64 + self.length as u64
+    }
+}
+
+/// The data after `IEND` and where it starts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrailerInfo {
+    pub offset: u64,
+    pub length: u64,
+    pub data: Data,
+}
+
+impl TrailerInfo {
+    /// Describes `trailer`, found at `offset` in its file.
+    pub fn new(trailer: &[u8], offset: u64) -> TrailerInfo {
+        TrailerInfo {
+            offset,
+            length: trailer.len() as u64,
+            data: Data::new(trailer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_data_encoding() {
+        assert_eq!(Data::new(b"hello\tworld\n"), Data::Utf8("hello\tworld\n".to_string()));
+        assert_eq!(Data::new("héllo".as_bytes()), Data::Utf8("héllo".to_string()));
+        assert_eq!(Data::new(&[0xff, 0x00]), Data::Base64("/wA=".to_string()));
+        assert_eq!(Data::new(b"nul\0"), Data::Base64("bnVsAA==".to_string()));
+        assert_eq!(Data::new(b""), Data::Utf8(String::new()));
+    }
+
+    #[test]
+    fn test_chunk_info() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"secret".to_vec());
+        let info = ChunkInfo::new(&chunk, 33);
+
+        assert_eq!(info.length, 6);
+        assert_eq!(info.chunk_type, "RuSt");
+        assert!(info.critical);
+        assert!(!info.public);
+        assert!(info.safe_to_copy);
+        assert_eq!(info.crc, chunk.crc());
+        assert_eq!(info.next_offset(), 33 + 12 + 6);
+    }
+
+    #[test]
+    fn test_schema() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![1, 2, 3]);
+        let json = serde_json::to_value(ChunkInfo::new(&chunk, 8)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "offset": 8,
+                "length": 3,
+                "type": "ruSt",
+                "critical": false,
+                "public": false,
+                "safe_to_copy": true,
+                "crc": chunk.crc(),
+                "data": { "encoding": "base64", "value": "AQID" },
+            })
+        );
+    }
+}
@@ -0,0 +1,631 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+
+use flate2::bufread::ZlibDecoder;
+use serde::Serialize;
+
+use crate::chunk::Chunk;
+use crate::ihdr::ColorType;
+use crate::png::{Png, KNOWN_ANCILLARY};
+use crate::text::TextChunk;
+use crate::{lsb, payload, split};
+
+/// Chunks whose data is normally compressed, so high entropy is expected.
+const COMPRESSED: [&[u8; 4]; 4] = [b"iCCP", b"iTXt", b"zTXt", b"fdAT"];
+
+/// Text longer than this, in bytes, is more than metadata usually needs.
+pub const LARGE_TEXT_LENGTH: usize = 16 * 1024;
+
+/// Chunk data shorter than this is too small to judge its entropy.
+const MIN_ENTROPY_LENGTH: usize = 32;
+
+/// Fraction of the highest entropy possible for the data's length above
+/// which data looks compressed or encrypted.
+const HIGH_ENTROPY_RATIO: f64 = 0.9;
+
+/// Chi-square p-value above which an LSB plane looks like it was replaced
+/// with random bits.
+const CHI_SQUARE_THRESHOLD: f64 = 0.95;
+
+/// RS estimate of the embedding rate above which an LSB plane is flagged.
+const RS_THRESHOLD: f64 = 0.1;
+
+/// Fewest samples, or RS groups, a statistical test is run on.
+const MIN_SAMPLES: usize = 1024;
+
+/// One reason a file looks like it hides something.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reason {
+    /// How much the reason adds to the file's score.
+    pub score: u32,
+    pub message: String,
+}
+
+/// The result of [`scan`]: a suspicion score from 0 to 100 and the reasons
+/// that add up to it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub score: u32,
+    pub reasons: Vec<Reason>,
+}
+
+impl Report {
+    fn from_reasons(reasons: Vec<Reason>) -> Report {
+        let score = reasons.iter().map(|reason| reason.score).sum::<u32>().min(100);
+        Report { score, reasons }
+    }
+}
+
+/// Looks for signs of hidden data: unusual chunks, chunk data that looks
+/// encrypted, data after `IEND` or after the image data's zlib stream,
+/// oversized text, and statistical traces of LSB embedding in the pixels.
+pub fn scan(png: &Png) -> Report {
+    let mut reasons = Vec::new();
+
+    check_chunks(png, &mut reasons);
+
+    if !png.trailer().is_empty() {
+        reasons.push(reason(
+            40,
+            format!("{} bytes of data after IEND", png.trailer().len()),
+        ));
+    }
+
+    check_image_data(png, &mut reasons);
+    check_lsb_planes(png, &mut reasons);
+
+    Report::from_reasons(reasons)
+}
+
+fn check_chunks(png: &Png, reasons: &mut Vec<Reason>) {
+    let mut private: BTreeMap<String, usize> = BTreeMap::new();
+    let mut unknown: BTreeMap<String, usize> = BTreeMap::new();
+
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical() {
+            continue;
+        }
+
+        let name = chunk_type.to_string();
+        if !chunk_type.is_public() {
+            *private.entry(name.clone()).or_insert(0) += 1;
+        } else if !KNOWN_ANCILLARY.contains(&&chunk_type.bytes()) {
+            *unknown.entry(name.clone()).or_insert(0) += 1;
+        }
+
+        let data = chunk.data();
+        if payload::is_envelope(data) || split::is_piece(data) {
+            reasons.push(reason(40, format!("{} chunk holds a png-secret payload", name)));
+        } else if !COMPRESSED.contains(&&chunk_type.bytes()) && is_high_entropy(data) {
+            reasons.push(reason(
+                25,
+                format!(
+                    "{} chunk data looks random ({:.2} bits per byte over {} bytes)",
+                    name,
+                    entropy(data),
+                    data.len()
+                ),
+            ));
+        }
+
+        if TextChunk::is_text_chunk(chunk_type) {
+            check_text(chunk, reasons);
+        }
+    }
+
+    for (name, count) in private {
+        reasons.push(reason(20, format!("private ancillary chunk {} (x{})", name, count)));
+    }
+
+    for (name, count) in unknown {
+        reasons.push(reason(10, format!("unknown public ancillary chunk {} (x{})", name, count)));
+    }
+}
+
+fn check_text(chunk: &Chunk, reasons: &mut Vec<Reason>) {
+    match TextChunk::try_from(chunk) {
+        Ok(entry) if entry.text().len() > LARGE_TEXT_LENGTH => reasons.push(reason(
+            15,
+            format!(
+                "{} chunk '{}' holds {} bytes of text",
+                chunk.chunk_type(),
+                entry.keyword(),
+                entry.text().len()
+            ),
+        )),
+        Ok(_) => {}
+        Err(err) => reasons.push(reason(
+            10,
+            format!("{} chunk is malformed: {}", chunk.chunk_type(), err),
+        )),
+    }
+}
+
+/// Flags data after the end of the zlib stream the `IDAT` chunks hold.
+/// Decoders stop at the end of the stream, so anything after it is unseen.
+fn check_image_data(png: &Png, reasons: &mut Vec<Reason>) {
+    let compressed: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.is_empty() {
+        return;
+    }
+
+    let mut decoder = ZlibDecoder::new(compressed.as_slice());
+    if io::copy(&mut decoder, &mut io::sink()).is_err() {
+        reasons.push(reason(10, "image data is not a valid zlib stream".to_string()));
+        return;
+    }
+
+    let rest = decoder.into_inner();
+    if !rest.is_empty() {
+        let streams = if ZlibDecoder::new(rest).read(&mut [0; 1]).is_ok() {
+            "another zlib stream"
+        } else {
+            "data"
+        };
+        reasons.push(reason(
+            35,
+            format!("{} bytes of {} after the image data's zlib stream", rest.len(), streams),
+        ));
+    }
+}
+
+fn check_lsb_planes(png: &Png, reasons: &mut Vec<Reason>) {
+    let Ok(ihdr) = png.ihdr() else {
+        return;
+    };
+    // Images lsb cannot read, such as palette images, are skipped
+    let Ok(planes) = lsb::sample_planes(png) else {
+        return;
+    };
+
+    let names: &[&str] = match ihdr.color_type {
+        ColorType::Grayscale | ColorType::Indexed => &["gray"],
+        ColorType::GrayscaleAlpha => &["gray", "alpha"],
+        ColorType::Truecolor => &["red", "green", "blue"],
+        ColorType::TruecolorAlpha => &["red", "green", "blue", "alpha"],
+    };
+
+    for (rows, name) in planes.iter().zip(names) {
+        let samples: Vec<u8> = rows.iter().flatten().copied().collect();
+        // Opaque images have a constant alpha plane, which says nothing
+        if samples.iter().all(|&sample| sample == samples[0]) {
+            continue;
+        }
+
+        if let Some((p_value, fraction)) = chi_square_attack(&samples) {
+            if p_value > CHI_SQUARE_THRESHOLD {
+                reasons.push(reason(
+                    25,
+                    format!(
+                        "chi-square test on the {} LSB plane suggests embedding \
+                         (p = {:.3} over the first {:.0}% of samples)",
+                        name,
+                        p_value,
+                        fraction * 100.0
+                    ),
+                ));
+            }
+        }
+
+        if let Some(rate) = rs_estimate(rows) {
+            if rate > RS_THRESHOLD {
+                reasons.push(reason(
+                    25,
+                    format!(
+                        "RS analysis estimates {:.0}% of {} LSBs carry data",
+                        rate * 100.0,
+                        name
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn reason(score: u32, message: String) -> Reason {
+    Reason { score, message }
+}
+
+/// Shannon entropy in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+
+    let length = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Whether `data` is about as random as data of its length can look.
+fn is_high_entropy(data: &[u8]) -> bool {
+    if data.len() < MIN_ENTROPY_LENGTH {
+        return false;
+    }
+
+    let highest = (data.len().min(256) as f64).log2();
+    entropy(data) >= highest * HIGH_ENTROPY_RATIO
+}
+
+/// The Westfeld-Pfitzmann chi-square attack. Replacing LSBs with random
+/// bits evens out the counts of each pair of values 2k and 2k+1, so the
+/// p-value of the pairs being equal climbs towards 1 over the embedded part
+/// of the image.
+///
+/// Payloads are written from the start of the image, so growing prefixes
+/// are tested and the highest p-value is returned with the prefix size.
+fn chi_square_attack(samples: &[u8]) -> Option<(f64, f64)> {
+    [1.0 / 64.0, 1.0 / 16.0, 1.0 / 4.0, 1.0]
+        .iter()
+        .filter_map(|&fraction| {
+            let length = (samples.len() as f64 * fraction) as usize;
+            if length < MIN_SAMPLES {
+                return None;
+            }
+
+            chi_square_p_value(&samples[..length]).map(|p_value| (p_value, fraction))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+fn chi_square_p_value(samples: &[u8]) -> Option<f64> {
+    let mut histogram = [0u64; 256];
+    for &sample in samples {
+        histogram[sample as usize] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+
+    if categories < 2 {
+        return None;
+    }
+
+    Some(gamma_q((categories - 1) as f64 / 2.0, statistic / 2.0))
+}
+
+/// Fridrich's RS analysis: estimates the fraction of LSBs that were
+/// replaced, from how flipping LSBs changes the smoothness of small groups
+/// of neighbouring samples.
+fn rs_estimate(rows: &[Vec<u8>]) -> Option<f64> {
+    const MASK: [i16; 4] = [0, 1, 1, 0];
+
+    // Regular and singular group counts for the mask and its negation, on
+    // the image and on the image with every LSB flipped
+    let mut counts = [[0usize; 2]; 4];
+    let mut groups = 0;
+
+    for row in rows {
+        for group in row.chunks_exact(MASK.len()) {
+            let original: [i16; 4] = std::array::from_fn(|i| group[i] as i16);
+            let flipped: [i16; 4] = std::array::from_fn(|i| original[i] ^ 1);
+            groups += 1;
+
+            let variants = [(original, 1), (original, -1), (flipped, 1), (flipped, -1)];
+            for (index, (pixels, sign)) in variants.into_iter().enumerate() {
+                let before = smoothness(&pixels);
+                let masked: [i16; 4] =
+                    std::array::from_fn(|i| flip(pixels[i], MASK[i] * sign));
+                let after = smoothness(&masked);
+
+                if after > before {
+                    counts[index][0] += 1;
+                } else if after < before {
+                    counts[index][1] += 1;
+                }
+            }
+        }
+    }
+
+    if groups < MIN_SAMPLES {
+        return None;
+    }
+
+    let difference = |index: usize| {
+        (counts[index][0] as f64 - counts[index][1] as f64) / groups as f64
+    };
+    let d0 = difference(0);
+    let d_negative0 = difference(1);
+    let d1 = difference(2);
+    let d_negative1 = difference(3);
+
+    let a = 2.0 * (d1 + d0);
+    let b = d_negative0 - d_negative1 - d1 - 3.0 * d0;
+    let c = d0 - d_negative0;
+
+    let x = if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        -c / b
+    } else {
+        // Noise can push the discriminant just below zero; the vertex is
+        // then the closest real answer
+        let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+        let first = (-b + root) / (2.0 * a);
+        let second = (-b - root) / (2.0 * a);
+        if first.abs() < second.abs() {
+            first
+        } else {
+            second
+        }
+    };
+
+    Some((x / (x - 0.5)).clamp(0.0, 1.0))
+}
+
+/// Sum of differences between neighbouring samples; lower is smoother.
+fn smoothness(pixels: &[i16; 4]) -> i16 {
+    pixels.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+}
+
+/// Applies the LSB flip for one mask entry: 1 swaps 2k and 2k+1, -1 swaps
+/// 2k-1 and 2k, 0 leaves the value alone.
+fn flip(value: i16, mask: i16) -> i16 {
+    match mask {
+        1 => value ^ 1,
+        -1 => ((value + 1) ^ 1) - 1,
+        _ => value,
+    }
+}
+
+/// The regularized upper incomplete gamma function Q(a, x), which gives
+/// chi-square p-values.
+fn gamma_q(a: f64, x: f64) -> f64 {
+    const ITERATIONS: usize = 1000;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        // Series for the lower function P(a, x)
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut denominator = a;
+        for _ in 0..ITERATIONS {
+            denominator += 1.0;
+            term *= x / denominator;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+
+        (1.0 - sum * prefactor).clamp(0.0, 1.0)
+    } else {
+        // Lentz's continued fraction for Q(a, x)
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for i in 1..ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+
+        (prefactor * fraction).clamp(0.0, 1.0)
+    }
+}
+
+/// Lanczos approximation of ln(Gamma(x)) for x > 0.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, &c)| sum + c / (x + i as f64 + 1.0));
+
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::Ihdr;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    /// Deterministic noise in 0..256.
+    fn noise(seed: &mut u32) -> u8 {
+        *seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (*seed >> 16) as u8
+    }
+
+    /// A smooth 8-bit grayscale image, with its pixel values built by
+    /// `pixel(x, y)`.
+    fn grayscale_png(size: u32, pixel: impl Fn(u32, u32) -> u8) -> Png {
+        let ihdr = Ihdr {
+            width: size,
+            height: size,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false,
+        };
+
+        let mut raw = Vec::new();
+        for y in 0..size {
+            raw.push(0);
+            raw.extend((0..size).map(|x| pixel(x, y)));
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+
+        Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), encoder.finish().unwrap()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    /// Even values along a gentle gradient with some noise, the kind of
+    /// histogram LSB embedding visibly evens out.
+    fn even_pixel(x: u32, y: u32) -> u8 {
+        let mut seed = x * 7919 + y * 104_729;
+        let jitter = noise(&mut seed) % 6;
+        (((x + y) / 2 + jitter as u32) as u8) & !1
+    }
+
+    /// Smooth waves with a little noise, closer to a photograph.
+    fn smooth_pixel(x: u32, y: u32) -> u8 {
+        let mut seed = x * 7919 + y * 104_729;
+        let wave = (x as f64 / 9.0).sin() * 60.0 + (y as f64 / 13.0).cos() * 50.0 + 120.0;
+        (wave as i32 + (noise(&mut seed) % 5) as i32).clamp(0, 255) as u8
+    }
+
+    /// Replaces the LSB of about `percent`% of pixels with a random bit.
+    fn embedded(pixel: fn(u32, u32) -> u8, percent: u32) -> impl Fn(u32, u32) -> u8 {
+        move |x, y| {
+            let mut seed = x * 31 + y * 17 + 5;
+            let value = pixel(x, y);
+            if (noise(&mut seed) as u32) * 100 / 256 < percent {
+                (value & !1) | (noise(&mut seed) & 1)
+            } else {
+                value
+            }
+        }
+    }
+
+    fn messages(report: &Report) -> Vec<&str> {
+        report.reasons.iter().map(|reason| reason.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_clean_images() {
+        let report = scan(&grayscale_png(128, even_pixel));
+        assert_eq!(report.score, 0, "{:?}", messages(&report));
+
+        // Evenly spread noise fools the chi-square test, but not RS
+        let report = scan(&grayscale_png(128, smooth_pixel));
+        assert!(!messages(&report)
+            .iter()
+            .any(|message| message.starts_with("RS analysis")));
+    }
+
+    #[test]
+    fn test_chi_square_detects_embedding() {
+        let report = scan(&grayscale_png(128, embedded(even_pixel, 100)));
+        assert!(messages(&report)
+            .iter()
+            .any(|message| message.starts_with("chi-square test on the gray LSB plane")));
+    }
+
+    #[test]
+    fn test_rs_detects_embedding() {
+        let report = scan(&grayscale_png(128, embedded(smooth_pixel, 25)));
+        assert!(messages(&report)
+            .iter()
+            .any(|message| message.starts_with("RS analysis estimates")));
+    }
+
+    #[test]
+    fn test_suspicious_chunks_and_trailer() {
+        let mut png = grayscale_png(8, even_pixel);
+        let mut seed = 1;
+        let random: Vec<u8> = (0..256).map(|_| noise(&mut seed)).collect();
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), random));
+        png.append_chunk(Chunk::new(ChunkType::from_str("aBCd").unwrap(), b"plain".to_vec()));
+        png.set_trailer(b"appended".to_vec()).unwrap();
+
+        let report = scan(&png);
+        let messages = messages(&report);
+        assert!(messages.iter().any(|message| message.starts_with("ruSt chunk data looks random")));
+        assert!(messages.contains(&"private ancillary chunk ruSt (x1)"));
+        assert!(messages.contains(&"unknown public ancillary chunk aBCd (x1)"));
+        assert!(messages.contains(&"8 bytes of data after IEND"));
+        assert_eq!(report.score, 95);
+    }
+
+    #[test]
+    fn test_payload_chunks() {
+        let mut png = grayscale_png(8, even_pixel);
+        let body = payload::wrap(payload::Envelope::default(), b"hidden");
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), body));
+        // Only the envelope marks a payload, not the headers inside it
+        png.append_chunk(Chunk::new(ChunkType::from_str("teSt").unwrap(), b"PSEC data".to_vec()));
+
+        let report = scan(&png);
+        let messages = messages(&report);
+        assert!(messages.contains(&"ruSt chunk holds a png-secret payload"));
+        assert!(!messages.contains(&"teSt chunk holds a png-secret payload"));
+    }
+
+    #[test]
+    fn test_second_zlib_stream() {
+        let mut png = grayscale_png(8, even_pixel);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"smuggled").unwrap();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            encoder.finish().unwrap(),
+        ));
+
+        let report = scan(&png);
+        assert!(messages(&report)[0].contains("another zlib stream"));
+    }
+
+    #[test]
+    fn test_gamma_q() {
+        // Q(1, x) is e^-x
+        assert!((gamma_q(1.0, 2.0) - (-2.0f64).exp()).abs() < 1e-12);
+        // Chi-square with 10 degrees of freedom: P(X > 18.307) = 0.05
+        assert!((gamma_q(5.0, 18.307 / 2.0) - 0.05).abs() < 1e-4);
+    }
+}
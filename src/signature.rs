@@ -0,0 +1,389 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ed25519_dalek::{Signer, Verifier};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// The chunk signatures are stored in. Ancillary and private, and unsafe to
+/// copy, since an editor that changes critical chunks breaks the signature.
+pub const CHUNK_TYPE: &str = "psIG";
+
+/// Marks chunk data produced by [`Signature::to_chunk`].
+const MAGIC: [u8; 4] = *b"PSSG";
+const VERSION: u8 = 1;
+
+/// Magic, version, scope, key ID, payload chunk type and signature.
+const LENGTH: usize = MAGIC.len() + 1 + 1 + KeyId::LENGTH + 4 + ed25519_dalek::SIGNATURE_LENGTH;
+
+/// Prefixed to everything signed, so a signature made here can't be passed
+/// off as one over some other message.
+const CONTEXT: &[u8] = b"png-secret signature v1\0";
+
+/// Identifies a public key: the first 8 bytes of its SHA-256 hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyId(pub [u8; 8]);
+
+impl KeyId {
+    const LENGTH: usize = 8;
+
+    /// The ID of `key`.
+    pub fn of(key: &VerifyingKey) -> KeyId {
+        let hash = Sha256::digest(key.as_bytes());
+        let mut id = [0; KeyId::LENGTH];
+        id.copy_from_slice(&hash[..KeyId::LENGTH]);
+        KeyId(id)
+    }
+}
+
+impl fmt::Display for KeyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// What a signature covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// The data of every chunk of this type, in file order.
+    Payload(ChunkType),
+    /// Every critical chunk, which is to say the image itself.
+    Critical,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Payload(chunk_type) => write!(f, "the {} payload", chunk_type),
+            Scope::Critical => write!(f, "the critical chunks"),
+        }
+    }
+}
+
+/// An Ed25519 signature over part of an image, with the ID of the key that
+/// made it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub key_id: KeyId,
+    pub scope: Scope,
+    pub signature: ed25519_dalek::Signature,
+}
+
+impl Signature {
+    /// Signs what `scope` covers in `png` with `key`.
+    pub fn sign(png: &Png, key: &SigningKey, scope: Scope) -> Result<Signature> {
+        let message = signed_message(png, &scope)?;
+        Ok(Signature {
+            key_id: KeyId::of(&key.verifying_key()),
+            signature: key.sign(&message),
+            scope,
+        })
+    }
+
+    /// Checks the signature against what it covers in `png`. Fails with
+    /// [`Error::SignatureMismatch`] if that changed after signing.
+    pub fn verify(&self, png: &Png, key: &VerifyingKey) -> Result<()> {
+        if KeyId::of(key) != self.key_id {
+            return Err(Error::InvalidArgument(format!(
+                "signature was made by key {}, not {}",
+                self.key_id,
+                KeyId::of(key)
+            )));
+        }
+
+        let mismatch = || Error::SignatureMismatch {
+            key_id: self.key_id.to_string(),
+        };
+        let message = match signed_message(png, &self.scope) {
+            Ok(message) => message,
+            // The signed payload is gone, which is as much a change as any
+            Err(Error::ChunkNotFound(_)) => return Err(mismatch()),
+            Err(err) => return Err(err),
+        };
+
+        key.verify(&message, &self.signature).map_err(|_| mismatch())
+    }
+
+    /// Serializes the signature as a [`CHUNK_TYPE`] chunk.
+    pub fn to_chunk(&self) -> Chunk {
+        let (scope, payload_type) = match &self.scope {
+            Scope::Payload(chunk_type) => (0, chunk_type.bytes()),
+            Scope::Critical => (1, [0; 4]),
+        };
+
+        let mut data = Vec::with_capacity(LENGTH);
+        data.extend_from_slice(&MAGIC);
+        data.push(VERSION);
+        data.push(scope);
+        data.extend_from_slice(&self.key_id.0);
+        data.extend_from_slice(&payload_type);
+        data.extend_from_slice(&self.signature.to_bytes());
+
+        let chunk_type = ChunkType::from_str(CHUNK_TYPE).expect("psIG is a valid chunk type");
+        Chunk::new(chunk_type, data)
+    }
+}
+
+impl TryFrom<&Chunk> for Signature {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if chunk.chunk_type().to_string() != CHUNK_TYPE || !data.starts_with(&MAGIC) {
+            return Err(Error::InvalidPayload("chunk is not a signature".to_string()));
+        }
+
+        if data.len() != LENGTH {
+            return Err(Error::InvalidPayload(format!(
+                "signature must be {} bytes, found {}",
+                LENGTH,
+                data.len()
+            )));
+        }
+
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::InvalidPayload(format!(
+                "unsupported signature version {}",
+                version
+            )));
+        }
+
+        let mut key_id = [0; KeyId::LENGTH];
+        key_id.copy_from_slice(&data[6..14]);
+        let scope = match data[5] {
+            0 => Scope::Payload(ChunkType::try_from([data[14], data[15], data[16], data[17]])?),
+            1 => Scope::Critical,
+            scope => {
+                return Err(Error::InvalidPayload(format!(
+                    "unknown signature scope {}",
+                    scope
+                )))
+            }
+        };
+
+        let mut signature = [0; ed25519_dalek::SIGNATURE_LENGTH];
+        signature.copy_from_slice(&data[18..]);
+
+        Ok(Signature {
+            key_id: KeyId(key_id),
+            scope,
+            signature: ed25519_dalek::Signature::from_bytes(&signature),
+        })
+    }
+}
+
+/// Every signature chunk in `png`, in file order, each read on its own so
+/// that one malformed or newer-version chunk doesn't hide the others.
+pub fn signatures(png: &Png) -> Vec<Result<Signature>> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == CHUNK_TYPE)
+        .map(Signature::try_from)
+        .collect()
+}
+
+/// A new random signing key.
+pub fn generate_key() -> SigningKey {
+    let mut secret = [0; ed25519_dalek::SECRET_KEY_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    SigningKey::from_bytes(&secret)
+}
+
+/// Formats a key as the hex digits read back by [`parse_signing_key`] and
+/// [`parse_verifying_key`].
+pub fn encode_key(key: &[u8; 32]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reads a signing key from a key file's contents: 32 raw bytes, or 64 hex
+/// digits.
+pub fn parse_signing_key(contents: &[u8]) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&key_bytes(contents)?))
+}
+
+/// Reads a public key from a key file's contents: 32 raw bytes, or 64 hex
+/// digits.
+pub fn parse_verifying_key(contents: &[u8]) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&key_bytes(contents)?)
+        .map_err(|_| Error::InvalidArgument("public key is not a valid Ed25519 key".to_string()))
+}
+
+fn key_bytes(contents: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0; 32];
+    if contents.len() == key.len() {
+        key.copy_from_slice(contents);
+        return Ok(key);
+    }
+
+    let invalid = || {
+        Error::InvalidArgument("key file must hold 32 bytes, raw or as 64 hex digits".to_string())
+    };
+    let digits = contents.trim_ascii();
+    if digits.len() != key.len() * 2 {
+        return Err(invalid());
+    }
+
+    for (byte, pair) in key.iter_mut().zip(digits.chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+
+    Ok(key)
+}
+
+/// The bytes a signature over `scope` signs: a context string, the scope,
+/// and each covered chunk's type, length and data.
+fn signed_message(png: &Png, scope: &Scope) -> Result<Vec<u8>> {
+    let mut message = CONTEXT.to_vec();
+    let chunks: Vec<&Chunk> = match scope {
+        Scope::Payload(chunk_type) => {
+            message.push(0);
+            let chunks: Vec<&Chunk> = png
+                .chunks()
+                .iter()
+                .filter(|chunk| chunk.chunk_type() == chunk_type)
+                .collect();
+            if chunks.is_empty() {
+                return Err(Error::ChunkNotFound(chunk_type.to_string()));
+            }
+            chunks
+        }
+        Scope::Critical => {
+            message.push(1);
+            png.chunks()
+                .iter()
+                .filter(|chunk| chunk.chunk_type().is_critical())
+                .collect()
+        }
+    };
+
+    for chunk in chunks {
+        message.extend_from_slice(&chunk.chunk_type().bytes());
+        message.extend_from_slice(&chunk.length().to_be_bytes());
+        message.extend_from_slice(chunk.data());
+    }
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            chunk("IDAT", b"image data"),
+            chunk("ruSt", b"payload"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = generate_key();
+        let mut png = testing_png();
+
+        for scope in [Scope::Critical, Scope::Payload(ChunkType::from_str("ruSt").unwrap())] {
+            let signature = Signature::sign(&png, &key, scope).unwrap();
+            png.append_chunk(signature.to_chunk());
+        }
+
+        let signatures: Vec<Signature> = signatures(&png).into_iter().flatten().collect();
+        assert_eq!(signatures.len(), 2);
+        for signature in &signatures {
+            assert_eq!(signature.key_id, KeyId::of(&key.verifying_key()));
+            signature.verify(&png, &key.verifying_key()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_altered_image() {
+        let key = generate_key();
+        let png = testing_png();
+        let critical = Signature::sign(&png, &key, Scope::Critical).unwrap();
+        let payload = Signature::sign(&png, &key, Scope::Payload("ruSt".parse().unwrap())).unwrap();
+
+        // New ancillary chunks change neither the image nor the payload
+        let mut extended = testing_png();
+        extended.append_chunk(chunk("tEXt", b"Comment\0added later"));
+        critical.verify(&extended, &key.verifying_key()).unwrap();
+        payload.verify(&extended, &key.verifying_key()).unwrap();
+
+        let mut altered = testing_png();
+        altered.remove_chunk("IDAT").unwrap();
+        assert!(matches!(
+            critical.verify(&altered, &key.verifying_key()),
+            Err(Error::SignatureMismatch { .. })
+        ));
+
+        let mut altered = testing_png();
+        altered.remove_chunk("ruSt").unwrap();
+        altered.append_chunk(chunk("ruSt", b"forged"));
+        assert!(matches!(
+            payload.verify(&altered, &key.verifying_key()),
+            Err(Error::SignatureMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_malformed_chunk_kept_apart() {
+        let key = generate_key();
+        let mut png = testing_png();
+        png.append_chunk(chunk(CHUNK_TYPE, b"PSSG\x09 from the future"));
+        png.append_chunk(Signature::sign(&png, &key, Scope::Critical).unwrap().to_chunk());
+
+        let signatures = signatures(&png);
+        assert_eq!(signatures.len(), 2);
+        assert!(signatures[0].is_err());
+        signatures[1].as_ref().unwrap().verify(&png, &key.verifying_key()).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let png = testing_png();
+        let signature = Signature::sign(&png, &generate_key(), Scope::Critical).unwrap();
+
+        assert!(signature.verify(&png, &generate_key().verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let png = testing_png();
+        let signature =
+            Signature::sign(&png, &generate_key(), Scope::Payload("ruSt".parse().unwrap()))
+                .unwrap();
+
+        let chunk = signature.to_chunk();
+        assert!(!chunk.chunk_type().is_safe_to_copy());
+        assert_eq!(Signature::try_from(&chunk).unwrap(), signature);
+    }
+
+    #[test]
+    fn test_key_files() {
+        let key = generate_key();
+        let hex = encode_key(&key.to_bytes());
+
+        let parsed = parse_signing_key(format!("{}\n", hex).as_bytes()).unwrap();
+        assert_eq!(parsed.to_bytes(), key.to_bytes());
+        assert_eq!(parse_signing_key(&key.to_bytes()).unwrap().to_bytes(), key.to_bytes());
+
+        let public = encode_key(key.verifying_key().as_bytes());
+        assert_eq!(parse_verifying_key(public.as_bytes()).unwrap(), key.verifying_key());
+
+        assert!(parse_signing_key(b"not a key").is_err());
+        assert!(parse_signing_key(&[b'g'; 64]).is_err());
+    }
+}
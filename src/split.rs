@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+
+use rand_core::{OsRng, RngCore};
+
+use crate::{Error, Result};
+
+/// Marks chunk data produced by [`split`].
+const MAGIC: [u8; 4] = *b"PSPL";
+const VERSION: u8 = 1;
+
+/// Magic, version, payload ID, sequence number and piece count.
+pub const HEADER_LENGTH: usize = MAGIC.len() + 1 + 8 + 4 + 4;
+
+/// Most pieces a payload may be split into. The count is read from chunk
+/// data, so it needs a limit before anything is sized by it.
+pub const MAX_PIECES: u32 = 1 << 20;
+
+/// How many missing pieces [`Error::IncompletePayload`] lists by number.
+const MAX_LISTED_MISSING: usize = 8;
+
+/// One chunk's share of a payload spread over several chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    /// Shared by every piece of the same payload.
+    pub payload_id: u64,
+    /// Position of this piece, starting at 0.
+    pub sequence: u32,
+    /// Number of pieces the payload was split into.
+    pub total: u32,
+    pub data: Vec<u8>,
+}
+
+impl Piece {
+    /// Serializes the piece as chunk data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.data.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.payload_id.to_be_bytes());
+        bytes.extend_from_slice(&self.sequence.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Piece {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if !is_piece(bytes) {
+            return Err(Error::InvalidPayload("data is not a split piece".to_string()));
+        }
+
+        if bytes.len() < HEADER_LENGTH {
+            return Err(Error::InvalidPayload("piece header is truncated".to_string()));
+        }
+
+        let version = bytes[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::InvalidPayload(format!(
+                "unsupported piece version {}",
+                version
+            )));
+        }
+
+        let mut id = [0; 8];
+        id.copy_from_slice(&bytes[5..13]);
+        let piece = Piece {
+            payload_id: u64::from_be_bytes(id),
+            sequence: u32::from_be_bytes([bytes[13], bytes[14], bytes[15], bytes[16]]),
+            total: u32::from_be_bytes([bytes[17], bytes[18], bytes[19], bytes[20]]),
+            data: bytes[HEADER_LENGTH..].to_vec(),
+        };
+
+        if piece.total > MAX_PIECES {
+            return Err(Error::InvalidPayload(format!(
+                "payload {:016x} claims {} pieces, more than the limit of {}",
+                piece.payload_id, piece.total, MAX_PIECES
+            )));
+        }
+
+        if piece.sequence >= piece.total {
+            return Err(Error::InvalidPayload(format!(
+                "piece {} of payload {:016x} is past its count of {}",
+                piece.sequence, piece.payload_id, piece.total
+            )));
+        }
+
+        Ok(piece)
+    }
+}
+
+/// A random ID for a new payload.
+pub fn new_payload_id() -> u64 {
+    OsRng.next_u64()
+}
+
+/// Whether chunk data starts with the header written by [`split`].
+pub fn is_piece(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Splits `payload` into pieces whose serialized form, header included, is
+/// at most `max_chunk_size` bytes.
+pub fn split(payload: &[u8], max_chunk_size: usize, payload_id: u64) -> Result<Vec<Piece>> {
+    if max_chunk_size <= HEADER_LENGTH {
+        return Err(Error::InvalidArgument(format!(
+            "chunk size must be more than the {}-byte piece header",
+            HEADER_LENGTH
+        )));
+    }
+
+    let piece_size = max_chunk_size - HEADER_LENGTH;
+    let total = payload.len().div_ceil(piece_size).max(1);
+    let total = match u32::try_from(total) {
+        Ok(total) if total <= MAX_PIECES => total,
+        _ => {
+            return Err(Error::InvalidArgument(format!(
+                "payload would need {} pieces, more than the limit of {}",
+                total, MAX_PIECES
+            )))
+        }
+    };
+
+    let mut pieces: Vec<Piece> = payload
+        .chunks(piece_size)
+        .enumerate()
+        .map(|(sequence, data)| Piece {
+            payload_id,
+            sequence: sequence as u32,
+            total,
+            data: data.to_vec(),
+        })
+        .collect();
+
+    if pieces.is_empty() {
+        pieces.push(Piece {
+            payload_id,
+            sequence: 0,
+            total,
+            data: Vec::new(),
+        });
+    }
+
+    Ok(pieces)
+}
+
+/// Groups pieces by payload ID and joins each group in sequence order. The
+/// result maps each payload ID to its data.
+///
+/// Pieces may arrive in any order. A payload with missing pieces is
+/// reported as [`Error::IncompletePayload`], and one claiming more than
+/// [`MAX_PIECES`] pieces as invalid.
+pub fn reassemble<I>(pieces: I) -> Result<BTreeMap<u64, Vec<u8>>>
+where
+    I: IntoIterator<Item = Piece>,
+{
+    let mut payloads: BTreeMap<u64, (u32, BTreeMap<u32, Vec<u8>>)> = BTreeMap::new();
+
+    for piece in pieces {
+        if piece.total > MAX_PIECES || piece.sequence >= piece.total {
+            return Err(Error::InvalidPayload(format!(
+                "piece {} of payload {:016x} doesn't fit a count of {} pieces",
+                piece.sequence, piece.payload_id, piece.total
+            )));
+        }
+
+        let (total, parts) = payloads
+            .entry(piece.payload_id)
+            .or_insert_with(|| (piece.total, BTreeMap::new()));
+
+        if *total != piece.total {
+            return Err(Error::InvalidPayload(format!(
+                "pieces of payload {:016x} disagree on the piece count",
+                piece.payload_id
+            )));
+        }
+
+        if let Some(existing) = parts.get(&piece.sequence) {
+            if *existing != piece.data {
+                return Err(Error::InvalidPayload(format!(
+                    "payload {:016x} has two different pieces numbered {}",
+                    piece.payload_id, piece.sequence
+                )));
+            }
+        }
+
+        parts.insert(piece.sequence, piece.data);
+    }
+
+    payloads
+        .into_iter()
+        .map(|(payload_id, (total, parts))| {
+            // Every sequence number is below the total, so this counts
+            // without walking all of them
+            let missing_count = total - parts.len() as u32;
+            if missing_count > 0 {
+                let missing = (0..total)
+                    .filter(|sequence| !parts.contains_key(sequence))
+                    .take(MAX_LISTED_MISSING)
+                    .collect();
+                return Err(Error::IncompletePayload {
+                    payload_id,
+                    missing_count,
+                    missing,
+                });
+            }
+
+            Ok((payload_id, parts.into_values().flatten().collect()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_sizes() {
+        let payload: Vec<u8> = (0..100).collect();
+        let pieces = split(&payload, HEADER_LENGTH + 30, 7).unwrap();
+
+        assert_eq!(pieces.len(), 4);
+        assert!(pieces.iter().all(|piece| piece.to_bytes().len() <= HEADER_LENGTH + 30));
+        assert!(pieces.iter().all(|piece| piece.total == 4));
+    }
+
+    #[test]
+    fn test_piece_round_trip() {
+        let piece = Piece {
+            payload_id: 0x0123456789abcdef,
+            sequence: 2,
+            total: 3,
+            data: b"piece".to_vec(),
+        };
+
+        let bytes = piece.to_bytes();
+        assert!(is_piece(&bytes));
+        assert_eq!(Piece::try_from(bytes.as_ref()).unwrap(), piece);
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let payload: Vec<u8> = (0..100).collect();
+        let mut pieces = split(&payload, HEADER_LENGTH + 30, 7).unwrap();
+        pieces.reverse();
+
+        let payloads = reassemble(pieces).unwrap();
+        assert_eq!(payloads[&7], payload);
+    }
+
+    #[test]
+    fn test_reassemble_multiple_payloads() {
+        let first = split(b"first payload", HEADER_LENGTH + 4, 1).unwrap();
+        let second = split(b"second payload", HEADER_LENGTH + 4, 2).unwrap();
+
+        let payloads = reassemble(first.into_iter().chain(second)).unwrap();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[&1], b"first payload");
+        assert_eq!(payloads[&2], b"second payload");
+    }
+
+    #[test]
+    fn test_missing_pieces() {
+        let mut pieces = split(b"a payload in several pieces", HEADER_LENGTH + 5, 9).unwrap();
+        pieces.remove(3);
+        pieces.remove(1);
+
+        match reassemble(pieces) {
+            Err(Error::IncompletePayload {
+                payload_id,
+                missing_count,
+                missing,
+            }) => {
+                assert_eq!(payload_id, 9);
+                assert_eq!(missing_count, 2);
+                assert_eq!(missing, vec![1, 3]);
+            }
+            other => panic!("expected missing pieces, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_oversized_total() {
+        let piece = Piece {
+            payload_id: 5,
+            sequence: 0,
+            total: u32::MAX,
+            data: b"crafted".to_vec(),
+        };
+
+        assert!(Piece::try_from(piece.to_bytes().as_ref()).is_err());
+        assert!(matches!(reassemble([piece]), Err(Error::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn test_missing_pieces_truncated() {
+        let piece = Piece {
+            payload_id: 5,
+            sequence: 0,
+            total: MAX_PIECES,
+            data: b"one of many".to_vec(),
+        };
+
+        match reassemble([piece]) {
+            Err(Error::IncompletePayload {
+                missing_count,
+                missing,
+                ..
+            }) => {
+                assert_eq!(missing_count, MAX_PIECES - 1);
+                assert_eq!(missing, (1..=MAX_LISTED_MISSING as u32).collect::<Vec<u32>>());
+            }
+            other => panic!("expected missing pieces, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let pieces = split(b"", 64, 3).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(reassemble(pieces).unwrap()[&3], b"");
+    }
+
+    #[test]
+    fn test_chunk_size_too_small() {
+        assert!(split(b"payload", HEADER_LENGTH, 1).is_err());
+    }
+}
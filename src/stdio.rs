@@ -0,0 +1,50 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Whether `path` is `-`, which names standard input when reading and
+/// standard output when writing.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Opens `path` for reading, or standard input for `-`.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    if is_stdio(path) {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
+
+/// Reads all of `path`, or of standard input for `-`.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        fs::read(path)
+    }
+}
+
+/// Creates `path` for writing, or returns standard output for `-`.
+pub fn create(path: &Path) -> io::Result<Box<dyn Write>> {
+    if is_stdio(path) {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(File::create(path)?))
+    }
+}
+
+/// Prints a command's output: on standard output, or on standard error
+/// when standard output carries a PNG and text would corrupt it.
+pub fn print(output: &[u8], to_stderr: bool) -> io::Result<()> {
+    if to_stderr {
+        io::stderr().write_all(output)
+    } else {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(output)?;
+        stdout.flush()
+    }
+}
@@ -0,0 +1,377 @@
+use std::io::{self, Read, Write};
+
+use crate::chunk::{read_u32, Chunk, CHUNK_CRC};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// The length and type of a chunk, read ahead of its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub length: u32,
+    pub chunk_type: ChunkType,
+}
+
+/// Reads a PNG one chunk at a time from any [`Read`], so files never need
+/// to be held in memory whole.
+///
+/// Iterating yields complete [`Chunk`]s. For chunks too large to buffer,
+/// call [`next_header`](ChunkReader::next_header) and then stream the data
+/// with [`copy_data`](ChunkReader::copy_data), or skip it by asking for the
+/// next header.
+///
+/// Reading stops at `IEND`. Anything after it is left for
+/// [`read_trailer`](ChunkReader::read_trailer).
+pub struct ChunkReader<R> {
+    reader: R,
+    pending: Option<ChunkHeader>,
+    done: bool,
+    ended: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks the PNG signature.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut signature = [0; 8];
+        let read = read_up_to(&mut reader, &mut signature)?;
+        if read < signature.len() || signature != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(ChunkReader {
+            reader,
+            pending: None,
+            done: false,
+            ended: false,
+        })
+    }
+
+    /// Reads the length and type of the next chunk, or `None` after `IEND`
+    /// or at the end of the input. Data left unread from the previous chunk
+    /// is skipped, but its CRC is still checked.
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+        if self.pending.is_some() {
+            self.copy_data(&mut io::sink())?;
+        }
+
+        if self.ended {
+            return Ok(None);
+        }
+
+        let mut bytes = [0; 8];
+        let read = read_up_to(&mut self.reader, &mut bytes)?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        if read < bytes.len() {
+            return Err(Error::TruncatedChunk {
+                needed: Chunk::METADATA_LENGTH,
+                available: read,
+            });
+        }
+
+        let length = read_u32(&bytes);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::ChunkTooLong(length));
+        }
+
+        let chunk_type = ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?;
+        self.ended = chunk_type.bytes() == *b"IEND";
+        let header = ChunkHeader { length, chunk_type };
+        self.pending = Some(header.clone());
+
+        Ok(Some(header))
+    }
+
+    /// Reads the data of the chunk whose header was just returned.
+    ///
+    /// # Panics
+    ///
+    /// If there is no header whose data has not been read yet.
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.copy_data(&mut data)?;
+        Ok(data)
+    }
+
+    /// Streams the data of the chunk whose header was just returned into
+    /// `writer`, then checks and returns its CRC.
+    ///
+    /// # Panics
+    ///
+    /// If there is no header whose data has not been read yet.
+    pub fn copy_data<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<u32> {
+        let header = self
+            .pending
+            .take()
+            .expect("copy_data called without a pending chunk header");
+
+        let mut digest = CHUNK_CRC.digest();
+        digest.update(&header.chunk_type.bytes());
+
+        let mut data = (&mut self.reader).take(header.length as u64);
+        let mut buffer = [0; 8192];
+        let mut copied = 0;
+        loop {
+            let read = match data.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            digest.update(&buffer[..read]);
+            writer.write_all(&buffer[..read])?;
+            copied += read;
+        }
+
+        let mut crc_bytes = [0; 4];
+        let crc_read = read_up_to(&mut self.reader, &mut crc_bytes)?;
+        if copied < header.length as usize || crc_read < crc_bytes.len() {
+            return Err(Error::TruncatedChunk {
+                needed: Chunk::METADATA_LENGTH + header.length as usize,
+                available: 8 + copied + crc_read,
+            });
+        }
+
+        let expected = digest.finalize();
+        let found = read_u32(&crc_bytes);
+        if expected != found {
+            return Err(Error::CrcMismatch { expected, found });
+        }
+
+        Ok(found)
+    }
+
+    /// Whether `IEND` has been read.
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Reads everything after `IEND`, skipping any chunks not yet read.
+    /// Empty if the input has no `IEND` or nothing follows it.
+    pub fn read_trailer(&mut self) -> Result<Vec<u8>> {
+        let mut trailer = Vec::new();
+        self.copy_trailer(&mut trailer)?;
+        Ok(trailer)
+    }
+
+    /// Streams everything after `IEND` into `writer`, returning how many
+    /// bytes were copied.
+    pub fn copy_trailer<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<u64> {
+        while self.next_header()?.is_some() {}
+
+        if !self.ended {
+            return Ok(0);
+        }
+
+        Ok(io::copy(&mut self.reader, writer)?)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Result<Chunk>> {
+        if self.done {
+            return None;
+        }
+
+        let chunk = self.next_header().and_then(|header| match header {
+            Some(header) => Ok(Some(Chunk::new(header.chunk_type, self.read_data()?))),
+            None => Ok(None),
+        });
+
+        match chunk {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Writes a PNG one chunk at a time to any [`Write`].
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes the PNG signature.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(ChunkWriter { writer })
+    }
+
+    /// Writes a chunk held in memory.
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Copies the chunk whose header `reader` just returned without
+    /// buffering its data.
+    pub fn copy_chunk<R: Read>(
+        &mut self,
+        header: &ChunkHeader,
+        reader: &mut ChunkReader<R>,
+    ) -> Result<()> {
+        self.writer.write_all(&header.length.to_be_bytes())?;
+        self.writer.write_all(&header.chunk_type.bytes())?;
+        let crc = reader.copy_data(&mut self.writer)?;
+        self.writer.write_all(&crc.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Writes raw bytes after the chunks, where decoders stop reading.
+    pub fn write_trailer(&mut self, trailer: &[u8]) -> Result<()> {
+        Ok(self.writer.write_all(trailer)?)
+    }
+
+    /// Copies whatever `reader` has after its `IEND`, skipping any chunks it
+    /// has not read yet.
+    pub fn copy_trailer<R: Read>(&mut self, reader: &mut ChunkReader<R>) -> Result<u64> {
+        reader.copy_trailer(&mut self.writer)
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Like `read_exact`, but reports how many bytes were read when the input
+/// ends early instead of failing.
+fn read_up_to<R: Read + ?Sized>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"middle".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let bytes = testing_png();
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_>>().unwrap();
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].data(), b"middle");
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let bytes = &testing_png()[1..];
+        assert!(matches!(
+            ChunkReader::new(bytes),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_skip_unread_data() {
+        let bytes = testing_png();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+
+        let first = reader.next_header().unwrap().unwrap();
+        assert_eq!(first.chunk_type.to_string(), "FrSt");
+        assert_eq!(first.length, 5);
+
+        let second = reader.next_header().unwrap().unwrap();
+        assert_eq!(second.chunk_type.to_string(), "miDl");
+        assert_eq!(reader.read_data().unwrap(), b"middle");
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let bytes = testing_png();
+        let reader = ChunkReader::new(&bytes[..bytes.len() - 16]).unwrap();
+        let chunks: Result<Vec<Chunk>> = reader.collect();
+
+        assert!(matches!(chunks, Err(Error::TruncatedChunk { .. })));
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut bytes = testing_png();
+        bytes[16] ^= 1;
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let chunks: Result<Vec<Chunk>> = reader.collect();
+
+        assert!(matches!(chunks, Err(Error::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_copy_round_trip() {
+        let bytes = testing_png();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+
+        while let Some(header) = reader.next_header().unwrap() {
+            writer.copy_chunk(&header, &mut reader).unwrap();
+        }
+
+        assert_eq!(writer.finish().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut bytes = testing_png();
+        bytes.extend_from_slice(b"after the end");
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+
+        let chunks: Vec<Chunk> = reader.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(reader.is_ended());
+        assert_eq!(reader.read_trailer().unwrap(), b"after the end");
+    }
+
+    #[test]
+    fn test_copy_trailer_skips_chunks() {
+        let mut bytes = testing_png();
+        bytes.extend_from_slice(b"after the end");
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+
+        reader.next_header().unwrap();
+        assert_eq!(writer.copy_trailer(&mut reader).unwrap(), 13);
+        assert!(writer.finish().unwrap().ends_with(b"after the end"));
+    }
+}
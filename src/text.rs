@@ -0,0 +1,360 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compress::DEFAULT_MAX_SIZE;
+use crate::{Error, Result};
+
+/// Longest keyword the PNG spec allows, in bytes.
+pub const MAX_KEYWORD_LENGTH: usize = 79;
+
+/// A textual metadata chunk, as other PNG tools read and write them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    /// `tEXt`: uncompressed Latin-1 text.
+    Text { keyword: String, text: String },
+    /// `zTXt`: zlib-compressed Latin-1 text.
+    Compressed { keyword: String, text: String },
+    /// `iTXt`: UTF-8 text, optionally compressed, with a language tag and a
+    /// translation of the keyword.
+    International {
+        keyword: String,
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+        text: String,
+    },
+}
+
+impl TextChunk {
+    /// Whether a chunk of this type holds text this module understands.
+    pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+        matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+    }
+
+    /// The keyword naming the entry, such as `Title` or `Comment`.
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text { keyword, .. }
+            | TextChunk::Compressed { keyword, .. }
+            | TextChunk::International { keyword, .. } => keyword,
+        }
+    }
+
+    /// The decoded, decompressed text.
+    pub fn text(&self) -> &str {
+        match self {
+            TextChunk::Text { text, .. }
+            | TextChunk::Compressed { text, .. }
+            | TextChunk::International { text, .. } => text,
+        }
+    }
+
+    /// The four-letter type this entry is stored under.
+    pub fn chunk_type(&self) -> ChunkType {
+        let bytes = match self {
+            TextChunk::Text { .. } => *b"tEXt",
+            TextChunk::Compressed { .. } => *b"zTXt",
+            TextChunk::International { .. } => *b"iTXt",
+        };
+
+        ChunkType::try_from(bytes).expect("text chunk types are valid")
+    }
+
+    /// Parses a `tEXt`, `zTXt` or `iTXt` chunk.
+    pub fn parse(chunk_type: &ChunkType, data: &[u8]) -> Result<TextChunk> {
+        let (keyword, rest) = split_at_nul(data, "keyword")?;
+        let keyword = from_latin1(keyword);
+        check_keyword(&keyword)?;
+
+        match &chunk_type.bytes() {
+            b"tEXt" => Ok(TextChunk::Text {
+                keyword,
+                text: from_latin1(rest),
+            }),
+            b"zTXt" => {
+                let (&method, compressed) = rest
+                    .split_first()
+                    .ok_or_else(|| invalid("zTXt chunk has no compression method"))?;
+                check_method(method)?;
+
+                Ok(TextChunk::Compressed {
+                    keyword,
+                    text: from_latin1(&inflate(compressed)?),
+                })
+            }
+            b"iTXt" => {
+                if rest.len() < 2 {
+                    return Err(invalid("iTXt chunk has no compression flags"));
+                }
+
+                let compressed = match rest[0] {
+                    0 => false,
+                    1 => true,
+                    flag => return Err(invalid(&format!("iTXt compression flag is {}", flag))),
+                };
+                check_method(rest[1])?;
+
+                let (language_tag, rest) = split_at_nul(&rest[2..], "language tag")?;
+                let (translated_keyword, text) = split_at_nul(rest, "translated keyword")?;
+                let text = if compressed {
+                    inflate(text)?
+                } else {
+                    text.to_vec()
+                };
+
+                Ok(TextChunk::International {
+                    keyword,
+                    compressed,
+                    language_tag: utf8(language_tag, "language tag")?,
+                    translated_keyword: utf8(translated_keyword, "translated keyword")?,
+                    text: utf8(&text, "text")?,
+                })
+            }
+            _ => Err(invalid(&format!("{} is not a text chunk", chunk_type))),
+        }
+    }
+
+    /// Serializes the entry as a chunk, checking the keyword and that
+    /// Latin-1 chunks only hold Latin-1 text.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        check_keyword(self.keyword())?;
+
+        let mut data = to_latin1(self.keyword(), "keyword")?;
+        data.push(0);
+
+        match self {
+            TextChunk::Text { text, .. } => {
+                let text = to_latin1(text, "tEXt text")?;
+                if text.contains(&0) {
+                    return Err(invalid("tEXt text cannot contain a null byte"));
+                }
+                data.extend_from_slice(&text);
+            }
+            TextChunk::Compressed { text, .. } => {
+                data.push(0);
+                data.extend_from_slice(&deflate(&to_latin1(text, "zTXt text")?)?);
+            }
+            TextChunk::International {
+                compressed,
+                language_tag,
+                translated_keyword,
+                text,
+                ..
+            } => {
+                if !language_tag
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+                {
+                    return Err(invalid(&format!("language tag '{}' is malformed", language_tag)));
+                }
+
+                if translated_keyword.contains('\0') {
+                    return Err(invalid("translated keyword cannot contain a null byte"));
+                }
+
+                data.push(*compressed as u8);
+                data.push(0);
+                data.extend_from_slice(language_tag.as_bytes());
+                data.push(0);
+                data.extend_from_slice(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend_from_slice(&deflate(text.as_bytes())?);
+                } else {
+                    data.extend_from_slice(text.as_bytes());
+                }
+            }
+        }
+
+        if data.len() > Chunk::MAX_LENGTH as usize {
+            return Err(invalid(&format!(
+                "text chunk would be {} bytes, over the limit of {}",
+                data.len(),
+                Chunk::MAX_LENGTH
+            )));
+        }
+
+        Ok(Chunk::new(self.chunk_type(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        TextChunk::parse(chunk.chunk_type(), chunk.data())
+    }
+}
+
+/// Whether `text` can be stored in a `tEXt` or `zTXt` chunk.
+pub fn is_latin1(text: &str) -> bool {
+    text.chars().all(|ch| (ch as u32) <= 0xff)
+}
+
+/// Keywords are 1-79 printable Latin-1 characters with no leading,
+/// trailing or consecutive spaces.
+fn check_keyword(keyword: &str) -> Result<()> {
+    let bytes = to_latin1(keyword, "keyword")?;
+
+    if bytes.is_empty() || bytes.len() > MAX_KEYWORD_LENGTH {
+        return Err(invalid(&format!(
+            "keyword must be 1 to {} bytes long",
+            MAX_KEYWORD_LENGTH
+        )));
+    }
+
+    if !bytes
+        .iter()
+        .all(|&byte| (32..=126).contains(&byte) || byte >= 161)
+    {
+        return Err(invalid(&format!("keyword '{}' has unprintable characters", keyword)));
+    }
+
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(invalid(&format!("keyword '{}' has misplaced spaces", keyword)));
+    }
+
+    Ok(())
+}
+
+fn check_method(method: u8) -> Result<()> {
+    if method != 0 {
+        return Err(invalid(&format!("unknown compression method {}", method)));
+    }
+
+    Ok(())
+}
+
+fn split_at_nul<'a>(data: &'a [u8], field: &str) -> Result<(&'a [u8], &'a [u8])> {
+    let position = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid(&format!("{} is not null-terminated", field)))?;
+
+    Ok((&data[..position], &data[position + 1..]))
+}
+
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+fn to_latin1(text: &str, field: &str) -> Result<Vec<u8>> {
+    if !is_latin1(text) {
+        return Err(invalid(&format!("{} is not Latin-1 text", field)));
+    }
+
+    Ok(text.chars().map(|ch| ch as u8).collect())
+}
+
+fn utf8(bytes: &[u8], field: &str) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid(&format!("{} is not UTF-8", field)))
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut text = Vec::new();
+    ZlibDecoder::new(data)
+        .take(DEFAULT_MAX_SIZE as u64 + 1)
+        .read_to_end(&mut text)
+        .map_err(|err| invalid(&format!("compressed text is corrupt: {}", err)))?;
+
+    if text.len() > DEFAULT_MAX_SIZE {
+        return Err(Error::PayloadTooLarge {
+            limit: DEFAULT_MAX_SIZE,
+        });
+    }
+
+    Ok(text)
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidTextChunk(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(entry: TextChunk) {
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        round_trip(TextChunk::Text {
+            keyword: "Comment".to_string(),
+            text: "Caf\u{e9} au lait".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        round_trip(TextChunk::Compressed {
+            keyword: "Description".to_string(),
+            text: "a long description ".repeat(20),
+        });
+    }
+
+    #[test]
+    fn test_international_round_trip() {
+        for compressed in [false, true] {
+            round_trip(TextChunk::International {
+                keyword: "Title".to_string(),
+                compressed,
+                language_tag: "ja".to_string(),
+                translated_keyword: "\u{984c}\u{540d}".to_string(),
+                text: "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f}".to_string(),
+            });
+        }
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let entry = TextChunk::Text {
+            keyword: "Author".to_string(),
+            text: "Tsujin".to_string(),
+        };
+        let chunk = entry.to_chunk().unwrap();
+
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Author\0Tsujin");
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        for keyword in ["", " Leading", "Trailing ", "Two  spaces", &"k".repeat(80)] {
+            let entry = TextChunk::Text {
+                keyword: keyword.to_string(),
+                text: "text".to_string(),
+            };
+            assert!(entry.to_chunk().is_err(), "accepted '{}'", keyword);
+        }
+    }
+
+    #[test]
+    fn test_non_latin1_text() {
+        let entry = TextChunk::Text {
+            keyword: "Comment".to_string(),
+            text: "\u{3042}".to_string(),
+        };
+
+        assert!(matches!(entry.to_chunk(), Err(Error::InvalidTextChunk(_))));
+    }
+
+    #[test]
+    fn test_missing_separator() {
+        let chunk_type = ChunkType::try_from(*b"tEXt").unwrap();
+        assert!(TextChunk::parse(&chunk_type, b"no separator").is_err());
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::chunk::{read_u32, Chunk, CHUNK_CRC};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+/// Critical chunks defined by the PNG spec. Any other critical chunk makes
+/// the image undecodable.
+const KNOWN_CRITICAL: [&[u8; 4]; 4] = [b"IHDR", b"PLTE", b"IDAT", b"IEND"];
+
+/// Chunks that may appear at most once.
+const SINGLE_INSTANCE: [&[u8; 4]; 16] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"cICP", b"gAMA", b"iCCP", b"mDCV", b"cLLI", b"sBIT",
+    b"sRGB", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"tIME",
+];
+
+/// Ancillary chunks that must come before both `PLTE` and `IDAT`.
+const BEFORE_PLTE: [&[u8; 4]; 7] =
+    [b"cHRM", b"cICP", b"gAMA", b"iCCP", b"mDCV", b"sBIT", b"sRGB"];
+
+/// Ancillary chunks that must come after `PLTE` but before `IDAT`.
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+
+/// Ancillary chunks that must come before `IDAT`, with no rule about `PLTE`.
+const BEFORE_IDAT: [&[u8; 4]; 3] = [b"pHYs", b"sPLT", b"eXIf"];
+
+/// How serious a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Legal, but unusual enough to be worth a look.
+    Warning,
+    /// Breaks the PNG spec; strict decoders will reject the file.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// One problem found by [`validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Byte offset of the chunk or data the finding is about, if it has one.
+    pub offset: Option<u64>,
+    /// Type of the chunk the finding is about, if any.
+    pub chunk_type: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        if let Some(chunk_type) = &self.chunk_type {
+            write!(f, " ({})", chunk_type)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A chunk that was read far enough to check where it sits in the file.
+struct Located {
+    offset: usize,
+    chunk_type: ChunkType,
+}
+
+/// Checks the structure of a PNG file without decoding the image: the
+/// signature, every chunk's framing and CRC, chunk types, chunk ordering and
+/// data after `IEND`.
+///
+/// Unlike parsing into a [`Png`], a bad CRC does not stop the check, so one
+/// call reports every problem it can find. Framing errors, such as a
+/// truncated chunk, end the walk since nothing after them can be located.
+pub fn validate(bytes: &[u8]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        findings.push(error(Some(0), None, "file does not start with the PNG signature"));
+        return findings;
+    }
+
+    let mut offset = Png::STANDARD_HEADER.len();
+    let mut chunks = Vec::new();
+    let mut ihdr = None;
+
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        if rest.len() < Chunk::METADATA_LENGTH {
+            findings.push(error(
+                Some(offset),
+                None,
+                &format!("chunk header is truncated, only {} bytes left", rest.len()),
+            ));
+            break;
+        }
+
+        let length = read_u32(rest);
+        let type_bytes = [rest[4], rest[5], rest[6], rest[7]];
+        let chunk_type = match ChunkType::try_from(type_bytes) {
+            Ok(chunk_type) => chunk_type,
+            Err(err) => {
+                findings.push(error(Some(offset), None, &err.to_string()));
+                break;
+            }
+        };
+        let name = Some(&chunk_type);
+
+        if length > Chunk::MAX_LENGTH {
+            findings.push(error(
+                Some(offset),
+                name,
+                &format!("length {} is over the limit of {}", length, Chunk::MAX_LENGTH),
+            ));
+            break;
+        }
+
+        let end = Chunk::METADATA_LENGTH + length as usize;
+        if rest.len() < end {
+            findings.push(error(
+                Some(offset),
+                name,
+                &format!(
+                    "chunk is truncated, needs {} bytes but only {} are left",
+                    end,
+                    rest.len()
+                ),
+            ));
+            break;
+        }
+
+        let data = &rest[8..end - 4];
+        let stored = read_u32(&rest[end - 4..]);
+        let mut digest = CHUNK_CRC.digest();
+        digest.update(&type_bytes);
+        digest.update(data);
+        let computed = digest.finalize();
+        if stored != computed {
+            findings.push(error(
+                Some(offset),
+                name,
+                &format!("CRC is {:#010x}, data hashes to {:#010x}", stored, computed),
+            ));
+        }
+
+        if !chunk_type.is_reserved_bit_valid() {
+            findings.push(error(
+                Some(offset),
+                name,
+                "reserved bit is set (third letter is lowercase)",
+            ));
+        }
+
+        if chunk_type.is_critical() && !is_one_of(&chunk_type, &KNOWN_CRITICAL) {
+            findings.push(error(
+                Some(offset),
+                name,
+                "unknown critical chunk, decoders must refuse the image",
+            ));
+        }
+
+        if type_bytes == *b"IHDR" && ihdr.is_none() {
+            match Ihdr::try_from(data) {
+                Ok(header) => ihdr = Some(header),
+                Err(err) => findings.push(error(Some(offset), name, &err.to_string())),
+            }
+        }
+
+        if type_bytes == *b"IEND" && length != 0 {
+            findings.push(warning(Some(offset), name, "IEND should be empty"));
+        }
+
+        chunks.push(Located { offset, chunk_type });
+        offset += end;
+
+        if type_bytes == *b"IEND" {
+            break;
+        }
+    }
+
+    let ended = chunks.last().is_some_and(|chunk| chunk.chunk_type.bytes() == *b"IEND");
+    if ended && offset < bytes.len() {
+        findings.push(warning(
+            Some(offset),
+            None,
+            &format!("{} bytes of trailing data after IEND", bytes.len() - offset),
+        ));
+    }
+
+    check_order(&chunks, ihdr, offset, &mut findings);
+    findings
+}
+
+/// Whether any finding is an [`Severity::Error`].
+pub fn has_errors(findings: &[Finding]) -> bool {
+    findings.iter().any(|finding| finding.severity == Severity::Error)
+}
+
+/// Checks which chunks are present and where, given the chunks located by
+/// [`validate`] and the offset where the walk stopped.
+fn check_order(chunks: &[Located], ihdr: Option<Ihdr>, end: usize, findings: &mut Vec<Finding>) {
+    match chunks.first() {
+        Some(first) if first.chunk_type.bytes() == *b"IHDR" => {}
+        Some(first) => findings.push(error(
+            Some(first.offset),
+            Some(&first.chunk_type),
+            "IHDR must be the first chunk",
+        )),
+        None => findings.push(error(Some(end), None, "file has no chunks")),
+    }
+
+    if !chunks.iter().any(|chunk| chunk.chunk_type.bytes() == *b"IEND") {
+        findings.push(error(Some(end), None, "file does not end with IEND"));
+    }
+
+    let mut seen: HashMap<[u8; 4], usize> = HashMap::new();
+    let mut first_plte = None;
+    let mut first_idat = None;
+    let mut idat_ended = false;
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let bytes = chunk.chunk_type.bytes();
+        let name = Some(&chunk.chunk_type);
+        let offset = Some(chunk.offset);
+
+        let count = seen.entry(bytes).or_insert(0);
+        *count += 1;
+        if *count == 2 && is_one_of(&chunk.chunk_type, &SINGLE_INSTANCE) {
+            let message = "chunk may appear only once";
+            if chunk.chunk_type.is_critical() {
+                findings.push(error(offset, name, message));
+            } else {
+                findings.push(warning(offset, name, message));
+            }
+        }
+
+        if bytes != *b"IDAT" && index > 0 && chunks[index - 1].chunk_type.bytes() == *b"IDAT" {
+            idat_ended = true;
+        }
+
+        match &bytes {
+            b"IDAT" => {
+                if idat_ended {
+                    findings.push(error(offset, name, "IDAT chunks must be contiguous"));
+                    // Report each gap once
+                    idat_ended = false;
+                }
+                first_idat.get_or_insert(index);
+            }
+            b"PLTE" => {
+                if first_idat.is_some() {
+                    findings.push(error(offset, name, "PLTE must come before IDAT"));
+                }
+                first_plte.get_or_insert(index);
+            }
+            _ => {}
+        }
+
+        if is_one_of(&chunk.chunk_type, &BEFORE_PLTE) {
+            if first_plte.is_some() || first_idat.is_some() {
+                findings.push(warning(offset, name, "chunk must come before PLTE and IDAT"));
+            }
+        } else if is_one_of(&chunk.chunk_type, &AFTER_PLTE) {
+            if first_idat.is_some() {
+                findings.push(warning(offset, name, "chunk must come before IDAT"));
+            } else if first_plte.is_none()
+                && ihdr.is_some_and(|ihdr| ihdr.color_type == ColorType::Indexed)
+            {
+                findings.push(warning(offset, name, "chunk must come after PLTE"));
+            }
+        } else if is_one_of(&chunk.chunk_type, &BEFORE_IDAT) && first_idat.is_some() {
+            findings.push(warning(offset, name, "chunk must come before IDAT"));
+        }
+    }
+
+    if first_idat.is_none() {
+        findings.push(error(Some(end), None, "file has no IDAT chunk"));
+    }
+
+    if let Some(ihdr) = ihdr {
+        match ihdr.color_type {
+            ColorType::Indexed if first_plte.is_none() => findings.push(error(
+                None,
+                None,
+                "indexed-color image has no PLTE chunk",
+            )),
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                if let Some(index) = first_plte {
+                    findings.push(error(
+                        Some(chunks[index].offset),
+                        Some(&chunks[index].chunk_type),
+                        &format!("PLTE is not allowed in {} images", ihdr.color_type),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_one_of(chunk_type: &ChunkType, types: &[&[u8; 4]]) -> bool {
+    types.contains(&&chunk_type.bytes())
+}
+
+fn error(offset: Option<usize>, chunk_type: Option<&ChunkType>, message: &str) -> Finding {
+    finding(Severity::Error, offset, chunk_type, message)
+}
+
+fn warning(offset: Option<usize>, chunk_type: Option<&ChunkType>, message: &str) -> Finding {
+    finding(Severity::Warning, offset, chunk_type, message)
+}
+
+fn finding(
+    severity: Severity,
+    offset: Option<usize>,
+    chunk_type: Option<&ChunkType>,
+    message: &str,
+) -> Finding {
+    Finding {
+        severity,
+        offset: offset.map(|offset| offset as u64),
+        chunk_type: chunk_type.map(ChunkType::to_string),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr(color_type: ColorType) -> Chunk {
+        Ihdr {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false,
+        }
+        .to_chunk()
+    }
+
+    fn testing_bytes(chunks: Vec<Chunk>) -> Vec<u8> {
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    fn messages(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|finding| finding.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let bytes = testing_bytes(vec![
+            ihdr(ColorType::Truecolor),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("IDAT", b"first"),
+            chunk("IDAT", b"second"),
+            chunk("tEXt", b"Comment\0hi"),
+            chunk("IEND", b""),
+        ]);
+
+        assert_eq!(validate(&bytes), Vec::new());
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let findings = validate(b"GIF89a");
+        assert_eq!(findings.len(), 1);
+        assert!(has_errors(&findings));
+    }
+
+    #[test]
+    fn test_crc_mismatch_keeps_going() {
+        let mut bytes = testing_bytes(vec![
+            ihdr(ColorType::Grayscale),
+            chunk("IDAT", b"data"),
+            chunk("IEND", b""),
+        ]);
+        // Last byte of the IHDR CRC
+        bytes[8 + 12 + Ihdr::LENGTH - 1] ^= 1;
+
+        let findings = validate(&bytes);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].offset, Some(8));
+        assert_eq!(findings[0].chunk_type.as_deref(), Some("IHDR"));
+        assert!(findings[0].message.starts_with("CRC"));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let bytes = testing_bytes(vec![
+            ihdr(ColorType::Indexed),
+            chunk("IDAT", b"first"),
+            chunk("tEXt", b"Comment\0hi"),
+            chunk("IDAT", b"second"),
+            chunk("PLTE", &[0, 0, 0]),
+            chunk("IEND", b""),
+        ]);
+
+        let findings = validate(&bytes);
+        assert_eq!(
+            messages(&findings),
+            vec!["IDAT chunks must be contiguous", "PLTE must come before IDAT"]
+        );
+    }
+
+    #[test]
+    fn test_missing_and_duplicated_chunks() {
+        let bytes = testing_bytes(vec![
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("IEND", b""),
+        ]);
+
+        let findings = validate(&bytes);
+        assert_eq!(
+            messages(&findings),
+            vec![
+                "IHDR must be the first chunk",
+                "chunk may appear only once",
+                "file has no IDAT chunk"
+            ]
+        );
+        assert_eq!(findings[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_chunk_type_bits() {
+        let bytes = testing_bytes(vec![
+            ihdr(ColorType::Truecolor),
+            chunk("IDAT", b"data"),
+            chunk("RUST", b""),
+            chunk("rust", b""),
+            chunk("IEND", b""),
+        ]);
+
+        let findings = validate(&bytes);
+        assert_eq!(
+            messages(&findings),
+            vec![
+                "unknown critical chunk, decoders must refuse the image",
+                "reserved bit is set (third letter is lowercase)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_and_truncated_data() {
+        let mut bytes = testing_bytes(vec![
+            ihdr(ColorType::Truecolor),
+            chunk("IDAT", b"data"),
+            chunk("IEND", b""),
+        ]);
+        let length = bytes.len();
+        bytes.extend_from_slice(b"appended");
+
+        let findings = validate(&bytes);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].offset, Some(length as u64));
+
+        let findings = validate(&bytes[..length - 6]);
+        assert!(messages(&findings).contains(&"file does not end with IEND"));
+        assert!(has_errors(&findings));
+    }
+}